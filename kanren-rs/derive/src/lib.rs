@@ -0,0 +1,231 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Ident};
+
+/// Derive `FromTerm`, decoding structs from `(Name fields ...)` and enum variants from
+/// `(Variant fields ...)`. Types without fields are a bare `Name` atom.
+#[proc_macro_derive(FromTerm)]
+pub fn derive_from_term(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_term(&input).into()
+}
+
+/// Derive `IntoTerm` and `From<T> for Term`, using the same encoding as `FromTerm`
+#[proc_macro_derive(IntoTerm)]
+pub fn derive_into_term(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    into_term(&input).into()
+}
+
+/// Derive `FromTerm` and `IntoTerm`, and add pattern constructors that accept a term, e.g. a
+/// `Var`, for each field. Structs get `Point::pattern(x, y)`, enum variants get
+/// `Shape::circle_pattern(r)`.
+#[proc_macro_derive(Term)]
+pub fn derive_term(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let from_term = from_term(&input);
+    let into_term = into_term(&input);
+    let patterns = patterns(&input);
+    quote!(#from_term #into_term #patterns).into()
+}
+
+fn add_bounds(generics: &Generics, bound: TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+/// Construct `path` from `fields`, taking each field from `values`
+fn construct(path: TokenStream, fields: &Fields, values: &[TokenStream]) -> TokenStream {
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote!(#path { #(#names: #values),* })
+        }
+        Fields::Unnamed(_) => quote!(#path ( #(#values),* )),
+        Fields::Unit => path,
+    }
+}
+
+/// Pattern binding each field of `path` to `field_0`, `field_1`, ...
+fn destructure(path: TokenStream, fields: &Fields) -> (TokenStream, Vec<Ident>) {
+    let bindings: Vec<Ident> = (0..fields.len())
+        .map(|i| format_ident!("field_{}", i))
+        .collect();
+    let values: Vec<TokenStream> = bindings.iter().map(|b| quote!(#b)).collect();
+    (construct(path, fields, &values), bindings)
+}
+
+fn decode(path: TokenStream, tag: &str, fields: &Fields, expected: &str) -> TokenStream {
+    let arity = fields.len();
+    let values: Vec<TokenStream> = (0..arity)
+        .map(|i| quote!(::kanren_rs::FromTerm::from_term(&fields[#i])?))
+        .collect();
+    let value = construct(path, fields, &values);
+    quote! {{
+        #[allow(unused_variables)]
+        let fields = ::kanren_rs::convert::tagged_fields(term, #tag, #arity, #expected)?;
+        Ok(#value)
+    }}
+}
+
+fn from_term(input: &DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let expected = name.to_string();
+    let generics = add_bounds(&input.generics, quote!(::kanren_rs::FromTerm));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => decode(quote!(#name), &expected, &data.fields, &expected),
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|v| {
+                let variant = &v.ident;
+                let tag = variant.to_string();
+                let decode = decode(quote!(#name::#variant), &tag, &v.fields, &expected);
+                quote!(Some(#tag) => #decode,)
+            });
+            quote! {
+                match ::kanren_rs::convert::tag_of(term) {
+                    #(#arms)*
+                    _ => Err(::kanren_rs::FromTermError::mismatch(#expected, term)),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new_spanned(input, "FromTerm can't be derived for unions")
+                .to_compile_error()
+        }
+    };
+
+    quote! {
+        impl #impl_generics ::kanren_rs::FromTerm for #name #ty_generics #where_clause {
+            fn from_term(term: &::kanren_rs::Term) -> Result<Self, ::kanren_rs::FromTermError> {
+                #body
+            }
+        }
+    }
+}
+
+fn encode(tag: &str, bindings: &[Ident]) -> TokenStream {
+    quote! {
+        ::kanren_rs::convert::tagged_term(
+            #tag,
+            vec![#(::kanren_rs::IntoTerm::into_term(#bindings)),*],
+        )
+    }
+}
+
+fn into_term(input: &DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let generics = add_bounds(&input.generics, quote!(::kanren_rs::IntoTerm));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, bindings) = destructure(quote!(#name), &data.fields);
+            let encode = encode(&name.to_string(), &bindings);
+            quote! {
+                let #pattern = self;
+                #encode
+            }
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|v| {
+                let variant = &v.ident;
+                let (pattern, bindings) = destructure(quote!(#name::#variant), &v.fields);
+                let encode = encode(&variant.to_string(), &bindings);
+                quote!(#pattern => #encode,)
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new_spanned(input, "IntoTerm can't be derived for unions")
+                .to_compile_error()
+        }
+    };
+
+    quote! {
+        impl #impl_generics ::kanren_rs::IntoTerm for #name #ty_generics #where_clause {
+            fn into_term(self) -> ::kanren_rs::Term {
+                #body
+            }
+        }
+
+        impl #impl_generics ::core::convert::From<#name #ty_generics> for ::kanren_rs::Term #where_clause {
+            fn from(value: #name #ty_generics) -> Self {
+                ::kanren_rs::IntoTerm::into_term(value)
+            }
+        }
+    }
+}
+
+/// `snake_case` name of a `CamelCase` one, a run of capitals like `HTTP` is one word
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev = i.checked_sub(1).map(|i| chars[i]);
+            let next = chars.get(i + 1);
+            let word_start = match prev {
+                None => false,
+                Some(p) if p.is_uppercase() => next.is_some_and(|n| n.is_lowercase()),
+                Some(_) => true,
+            };
+            if word_start {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn pattern(vis: &syn::Visibility, name: Ident, tag: &str, fields: &Fields) -> TokenStream {
+    let args: Vec<Ident> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| f.ident.clone().unwrap_or_else(|| format_ident!("field_{}", i)))
+        .collect();
+    quote! {
+        #vis fn #name(#(#args: impl Into<::kanren_rs::Term>),*) -> ::kanren_rs::Term {
+            ::kanren_rs::convert::tagged_term(#tag, vec![#(#args.into()),*])
+        }
+    }
+}
+
+fn patterns(input: &DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let patterns = match &input.data {
+        Data::Struct(data) => {
+            vec![pattern(vis, format_ident!("pattern"), &name.to_string(), &data.fields)]
+        }
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|v| {
+                let pattern_name = format_ident!("{}_pattern", snake_case(&v.ident.to_string()));
+                pattern(vis, pattern_name, &v.ident.to_string(), &v.fields)
+            })
+            .collect(),
+        Data::Union(_) => return TokenStream::new(),
+    };
+
+    quote! {
+        #[allow(clippy::too_many_arguments)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#patterns)*
+        }
+    }
+}
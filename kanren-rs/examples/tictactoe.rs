@@ -28,6 +28,6 @@ fn main() {
     }));
 
     for state in states {
-        print!("{}\n", AsScheme(state));
+        println!("{}", AsScheme(state));
     }
 }
\ No newline at end of file
@@ -0,0 +1,739 @@
+// Interactive REPL, run with: cargo run --bin kanren
+//
+// > (define (appendo a b ab)
+//     (conde
+//       ((== a '()) (== ab b))
+//       ((fresh (h t r)
+//          (== a `(,h . ,t))
+//          (== ab `(,h . ,r))
+//          (appendo t b r)))))
+// > (run 3 (x y) (appendo x y '(1 2)))
+// (() (1 2))
+// ((1) (2))
+// ((1 2) ())
+// > :next
+// no more answers
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use kanren_rs::display::{output_dot, AsScheme, GoalTree, Residual};
+use kanren_rs::*;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Sexp {
+    Int(i32),
+    Str(String),
+    Sym(String),
+    List(Vec<Sexp>, Option<Box<Sexp>>),
+}
+
+impl Sexp {
+    fn wrap(name: &str, sexp: Sexp) -> Sexp {
+        Sexp::List(vec![Sexp::Sym(name.to_string()), sexp], None)
+    }
+
+    fn as_sym(&self) -> Option<&str> {
+        match self {
+            Sexp::Sym(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[Sexp]> {
+        match self {
+            Sexp::List(items, None) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' | ')' | '\'' | '`' | ',' => tokens.push(c.to_string()),
+            '[' => tokens.push("(".to_string()),
+            ']' => tokens.push(")".to_string()),
+            '"' => {
+                let mut s = String::from('"');
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.extend(chars.next()),
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(s);
+            }
+            c => {
+                let mut s = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()[]'`,\";".contains(c) {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(s);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse(tokens: &[String], pos: &mut usize) -> Result<Sexp, String> {
+    let token = tokens.get(*pos).ok_or("unexpected end of input")?;
+    *pos += 1;
+    match token.as_str() {
+        "(" => {
+            let mut items = Vec::new();
+            loop {
+                match tokens.get(*pos).map(String::as_str) {
+                    None => return Err("missing )".to_string()),
+                    Some(")") => {
+                        *pos += 1;
+                        return Ok(Sexp::List(items, None));
+                    }
+                    Some(".") if !items.is_empty() => {
+                        *pos += 1;
+                        let tail = parse(tokens, pos)?;
+                        if tokens.get(*pos).map(String::as_str) != Some(")") {
+                            return Err("expected ) after dotted tail".to_string());
+                        }
+                        *pos += 1;
+                        return Ok(match tail {
+                            Sexp::List(mut rest, tail) => {
+                                items.append(&mut rest);
+                                Sexp::List(items, tail)
+                            }
+                            tail => Sexp::List(items, Some(Box::new(tail))),
+                        });
+                    }
+                    Some(_) => items.push(parse(tokens, pos)?),
+                }
+            }
+        }
+        ")" => Err("unexpected )".to_string()),
+        "'" => Ok(Sexp::wrap("quote", parse(tokens, pos)?)),
+        "`" => Ok(Sexp::wrap("quasiquote", parse(tokens, pos)?)),
+        "," => Ok(Sexp::wrap("unquote", parse(tokens, pos)?)),
+        s if s.starts_with('"') => Ok(Sexp::Str(s[1..].to_string())),
+        s => Ok(s
+            .parse::<i32>()
+            .map(Sexp::Int)
+            .unwrap_or_else(|_| Sexp::Sym(s.to_string()))),
+    }
+}
+
+fn read_all(input: &str) -> Result<Vec<Sexp>, String> {
+    let tokens = tokenize(input)?;
+    let mut pos = 0;
+    let mut result = Vec::new();
+    while pos < tokens.len() {
+        result.push(parse(&tokens, &mut pos)?);
+    }
+    Ok(result)
+}
+
+/// Paren depth of the input, used to decide whether more lines are needed
+fn depth(input: &str) -> i32 {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut in_comment = false;
+    for c in input.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\n' if in_comment => in_comment = false,
+            _ if in_comment => (),
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if in_string => (),
+            ';' => in_comment = true,
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ => (),
+        }
+    }
+    depth
+}
+
+#[derive(Debug, Clone)]
+enum TermExpr {
+    Const(Term),
+    Var(String),
+    Cons(Box<TermExpr>, Box<TermExpr>),
+}
+
+#[derive(Debug, Clone)]
+enum GoalExpr {
+    Eq(TermExpr, TermExpr),
+    Neq(TermExpr, TermExpr),
+    Num(TermExpr),
+    Str(TermExpr),
+    Conj(Vec<GoalExpr>),
+    Conde(Vec<Vec<GoalExpr>>),
+    Fresh(Rc<[String]>, Rc<[GoalExpr]>),
+    Call(String, Vec<TermExpr>),
+}
+
+struct Relation {
+    params: Vec<String>,
+    body: Rc<[GoalExpr]>,
+}
+
+/// Relations defined so far, shared with the goals of running queries, which look them up when
+/// they call them
+#[derive(Clone, Default)]
+struct Relations {
+    defs: Rc<RefCell<HashMap<String, Rc<Relation>>>>,
+    // First error of a running query, like a call of a relation redefined while it was paused
+    error: Rc<RefCell<Option<String>>>,
+}
+
+impl Relations {
+    fn fail(&self, error: String) -> Goal {
+        self.error.borrow_mut().get_or_insert(error);
+        fail()
+    }
+}
+type Env = HashMap<String, Term>;
+
+fn quote(sexp: &Sexp) -> Term {
+    match sexp {
+        Sexp::Int(i) => Term::from(*i),
+        Sexp::Str(s) | Sexp::Sym(s) => Term::from(s),
+        Sexp::List(items, tail) => items.iter().rev().fold(
+            tail.as_ref().map(|t| quote(t)).unwrap_or(NULL),
+            |tail, item| cons(quote(item), tail),
+        ),
+    }
+}
+
+fn compile_quasi(sexp: &Sexp, scope: &HashSet<String>) -> Result<TermExpr, String> {
+    match sexp {
+        Sexp::List(items, None) if items.len() == 2 && items[0].as_sym() == Some("unquote") => {
+            compile_term(&items[1], scope)
+        }
+        Sexp::List(items, tail) => {
+            // The reader turns `(a . ,b)` into `(a unquote b)`
+            let (items, tail) = match (items.as_slice(), tail) {
+                ([init @ .., unquote, x], None) if unquote.as_sym() == Some("unquote") => {
+                    (init, compile_term(x, scope)?)
+                }
+                (items, Some(tail)) => (items, compile_quasi(tail, scope)?),
+                (items, None) => (items, TermExpr::Const(NULL)),
+            };
+            items.iter().rev().try_fold(tail, |tail, item| {
+                Ok(TermExpr::Cons(
+                    Box::new(compile_quasi(item, scope)?),
+                    Box::new(tail),
+                ))
+            })
+        }
+        _ => Ok(TermExpr::Const(quote(sexp))),
+    }
+}
+
+fn compile_term(sexp: &Sexp, scope: &HashSet<String>) -> Result<TermExpr, String> {
+    match sexp {
+        Sexp::Int(i) => Ok(TermExpr::Const(Term::from(*i))),
+        Sexp::Str(s) => Ok(TermExpr::Const(Term::from(s))),
+        Sexp::Sym(s) if scope.contains(s) => Ok(TermExpr::Var(s.clone())),
+        Sexp::Sym(s) => Err(format!("unbound variable {s}, use '{s} for a symbol")),
+        Sexp::List(items, None) => match items.split_first() {
+            None => Ok(TermExpr::Const(NULL)),
+            Some((head, args)) => match (head.as_sym(), args) {
+                (Some("quote"), [x]) => Ok(TermExpr::Const(quote(x))),
+                (Some("quasiquote"), [x]) => compile_quasi(x, scope),
+                (Some("cons"), [a, b]) => Ok(TermExpr::Cons(
+                    Box::new(compile_term(a, scope)?),
+                    Box::new(compile_term(b, scope)?),
+                )),
+                (Some("list"), items) => items.iter().rev().try_fold(
+                    TermExpr::Const(NULL),
+                    |tail, item| {
+                        Ok(TermExpr::Cons(
+                            Box::new(compile_term(item, scope)?),
+                            Box::new(tail),
+                        ))
+                    },
+                ),
+                _ => Err(format!("invalid term {sexp:?}")),
+            },
+        },
+        Sexp::List(_, Some(_)) => Err(format!("invalid term {sexp:?}")),
+    }
+}
+
+fn compile_goals(sexps: &[Sexp], scope: &HashSet<String>) -> Result<Vec<GoalExpr>, String> {
+    sexps.iter().map(|g| compile_goal(g, scope)).collect()
+}
+
+fn compile_goal(sexp: &Sexp, scope: &HashSet<String>) -> Result<GoalExpr, String> {
+    let items = sexp.as_list().ok_or(format!("invalid goal {sexp:?}"))?;
+    let (head, args) = items.split_first().ok_or("empty goal")?;
+    let head = head.as_sym().ok_or(format!("invalid goal {sexp:?}"))?;
+    let term = |x| compile_term(x, scope);
+
+    match (head, args) {
+        ("==", [a, b]) => Ok(GoalExpr::Eq(term(a)?, term(b)?)),
+        ("=/=", [a, b]) => Ok(GoalExpr::Neq(term(a)?, term(b)?)),
+        ("numbero", [a]) => Ok(GoalExpr::Num(term(a)?)),
+        ("stringo" | "symbolo", [a]) => Ok(GoalExpr::Str(term(a)?)),
+        ("conj", goals) => Ok(GoalExpr::Conj(compile_goals(goals, scope)?)),
+        ("disj", goals) => Ok(GoalExpr::Conde(
+            goals
+                .iter()
+                .map(|g| compile_goal(g, scope).map(|g| vec![g]))
+                .collect::<Result<_, _>>()?,
+        )),
+        ("conde", clauses) => Ok(GoalExpr::Conde(
+            clauses
+                .iter()
+                .map(|clause| {
+                    let goals = clause.as_list().ok_or("conde clause must be a list")?;
+                    compile_goals(goals, scope)
+                })
+                .collect::<Result<_, String>>()?,
+        )),
+        ("fresh", [vars, goals @ ..]) => {
+            let names = symbols(vars)?;
+            let mut scope = scope.clone();
+            scope.extend(names.iter().cloned());
+            Ok(GoalExpr::Fresh(names.into(), compile_goals(goals, &scope)?.into()))
+        }
+        (name, args) => Ok(GoalExpr::Call(
+            name.to_string(),
+            args.iter().map(term).collect::<Result<_, _>>()?,
+        )),
+    }
+}
+
+fn symbols(sexp: &Sexp) -> Result<Vec<String>, String> {
+    let items = sexp.as_list().ok_or("expected a list of variables")?;
+    items
+        .iter()
+        .map(|x| x.as_sym().map(str::to_string).ok_or(format!("invalid variable {x:?}")))
+        .collect()
+}
+
+fn build_term(expr: &TermExpr, env: &Env) -> Result<Term, String> {
+    match expr {
+        TermExpr::Const(t) => Ok(t.clone()),
+        TermExpr::Var(name) => env.get(name).cloned().ok_or(format!("unbound variable {name}")),
+        TermExpr::Cons(a, b) => Ok(cons(build_term(a, env)?, build_term(b, env)?)),
+    }
+}
+
+fn build_all(goals: &[GoalExpr], env: &Env, relations: &Relations) -> Goal {
+    if goals.is_empty() {
+        succeed()
+    } else {
+        all(goals.iter().map(|g| build(g, env, relations)))
+    }
+}
+
+fn build_fresh(
+    names: Rc<[String]>,
+    index: usize,
+    goals: Rc<[GoalExpr]>,
+    env: Env,
+    relations: Relations,
+) -> Goal {
+    if index == names.len() {
+        return build_all(&goals, &env, &relations);
+    }
+
+    fresh(move |x: Var| {
+        let mut env = env.clone();
+        env.insert(names[index].clone(), x.into());
+        build_fresh(
+            names.clone(),
+            index + 1,
+            goals.clone(),
+            env,
+            relations.clone(),
+        )
+    })
+}
+
+fn build(goal: &GoalExpr, env: &Env, relations: &Relations) -> Goal {
+    try_build(goal, env, relations).unwrap_or_else(|e| relations.fail(e))
+}
+
+fn try_build(goal: &GoalExpr, env: &Env, relations: &Relations) -> Result<Goal, String> {
+    let term = |t| build_term(t, env);
+    Ok(match goal {
+        GoalExpr::Eq(a, b) => eq(term(a)?, term(b)?),
+        GoalExpr::Neq(a, b) => neq(term(a)?, term(b)?),
+        GoalExpr::Num(a) => num(term(a)?),
+        GoalExpr::Str(a) => str(term(a)?),
+        GoalExpr::Conj(goals) => build_all(goals, env, relations),
+        GoalExpr::Conde(clauses) if clauses.is_empty() => eq(NULL, 0),
+        GoalExpr::Conde(clauses) => any(clauses.iter().map(|c| build_all(c, env, relations))),
+        GoalExpr::Fresh(names, goals) => build_fresh(
+            names.clone(),
+            0,
+            goals.clone(),
+            env.clone(),
+            relations.clone(),
+        ),
+        GoalExpr::Call(name, args) => {
+            let args: Vec<Term> = args.iter().map(term).collect::<Result<_, _>>()?;
+            let name = name.clone();
+            let relations = relations.clone();
+            // Looked up when called, the relation can have been redefined since the query started
+            jield(move || {
+                let Some(relation) = relations.defs.borrow().get(&name).cloned() else {
+                    return relations.fail(format!("unknown relation {name}"));
+                };
+                if relation.params.len() != args.len() {
+                    return relations.fail(format!(
+                        "{name} expects {} arguments, got {}",
+                        relation.params.len(),
+                        args.len()
+                    ));
+                }
+                let env = relation.params.iter().cloned().zip(args.clone()).collect();
+                build_all(&relation.body, &env, &relations)
+            })
+        }
+    })
+}
+
+/// Check that all reachable relation calls refer to defined relations with the right arity
+fn check_calls(
+    goals: &[GoalExpr],
+    relations: &HashMap<String, Rc<Relation>>,
+    visited: &mut HashSet<String>,
+) -> Result<(), String> {
+    for goal in goals {
+        match goal {
+            GoalExpr::Conj(goals) => check_calls(goals, relations, visited)?,
+            GoalExpr::Conde(clauses) => {
+                for goals in clauses {
+                    check_calls(goals, relations, visited)?;
+                }
+            }
+            GoalExpr::Fresh(_, goals) => check_calls(goals, relations, visited)?,
+            GoalExpr::Call(name, args) => {
+                let relation = relations
+                    .get(name)
+                    .ok_or(format!("unknown relation {name}"))?;
+                if relation.params.len() != args.len() {
+                    return Err(format!(
+                        "{name} expects {} arguments, got {}",
+                        relation.params.len(),
+                        args.len()
+                    ));
+                }
+                if visited.insert(name.clone()) {
+                    check_calls(&relation.body, relations, visited)?;
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+const RESULT: &str = " result";
+
+#[derive(Default)]
+struct Repl {
+    relations: Relations,
+    query: Option<Query<1>>,
+    tree: bool,
+    dot: Option<String>,
+}
+
+impl Repl {
+    fn eval(&mut self, sexp: &Sexp, out: &mut impl Write) -> Result<(), String> {
+        let items = sexp.as_list().ok_or(format!("expected a command, got {sexp:?}"))?;
+        match items {
+            [Sexp::Sym(define), signature, body @ ..] if define == "define" => {
+                let signature = symbols(signature)?;
+                let (name, params) = signature.split_first().ok_or("missing relation name")?;
+                let scope = params.iter().cloned().collect();
+                let body = compile_goals(body, &scope)?.into();
+                let relation = Relation {
+                    params: params.to_vec(),
+                    body,
+                };
+                self.relations
+                    .defs
+                    .borrow_mut()
+                    .insert(name.clone(), Rc::new(relation));
+                Ok(())
+            }
+            [Sexp::Sym(run), Sexp::Int(n), vars, goals @ ..] if run == "run" => {
+                self.run(vars, goals)?;
+                self.next(usize::try_from(*n).map_err(|e| e.to_string())?, out)
+            }
+            [Sexp::Sym(run), vars, goals @ ..] if run == "run*" => {
+                self.run(vars, goals)?;
+                self.next(usize::MAX, out)
+            }
+            _ => Err(format!("unknown command {sexp:?}")),
+        }
+    }
+
+    fn run(&mut self, vars: &Sexp, goals: &[Sexp]) -> Result<(), String> {
+        let names = symbols(vars)?;
+        let mut scope: HashSet<String> = names.iter().cloned().collect();
+        let mut goals = compile_goals(goals, &scope)?;
+        check_calls(&goals, &self.relations.defs.borrow(), &mut HashSet::new())?;
+
+        // Bind all query variables to a single list, so any number of them can be reified
+        scope.insert(RESULT.to_string());
+        let result = names.iter().rev().fold(TermExpr::Const(NULL), |tail, name| {
+            TermExpr::Cons(Box::new(TermExpr::Var(name.clone())), Box::new(tail))
+        });
+        goals.insert(0, GoalExpr::Eq(TermExpr::Var(RESULT.to_string()), result));
+        let goal = GoalExpr::Fresh(names.into(), goals.into());
+
+        let relations = self.relations.clone();
+        self.query = Some(query(move |q: Var| {
+            let env = Env::from([(RESULT.to_string(), q.into())]);
+            build(&goal, &env, &relations)
+        }));
+        Ok(())
+    }
+
+    fn next(&mut self, n: usize, out: &mut impl Write) -> Result<(), String> {
+        let query = self.query.as_mut().ok_or("no active query")?;
+        let mut count = 0;
+        while count < n {
+            let state = query.next();
+            if let Some(error) = self.relations.error.borrow_mut().take() {
+                self.query = None;
+                return Err(error);
+            }
+            let Some(state) = state else {
+                break;
+            };
+            count += 1;
+            let Canonical {
+                terms: [answer],
+                constraints,
+                delayed,
+            } = canonical::<1>(&state);
+            let residual = Residual {
+                constraints: &constraints,
+                delayed: &delayed,
+            };
+            let result = if residual.is_empty() {
+                writeln!(out, "{}", AsScheme(answer))
+            } else {
+                writeln!(out, "{} : {}", AsScheme(answer), AsScheme(residual))
+            };
+            result.map_err(|e| e.to_string())?;
+        }
+        if count < n && n != usize::MAX || count == 0 {
+            writeln!(out, "no more answers").map_err(|e| e.to_string())?;
+        }
+
+        if self.tree {
+            write!(out, "{}", GoalTree(&query.goal)).map_err(|e| e.to_string())?;
+        }
+        if let Some(path) = &self.dot {
+            let mut file = File::create(path).map_err(|e| e.to_string())?;
+            output_dot(&mut file, &query.goal).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, line: &str, out: &mut impl Write) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(":q" | ":quit"), None) => return Ok(false),
+            (Some(":n" | ":next"), n) => {
+                let n = n.map(str::parse).transpose().map_err(|e| format!("{e}"))?;
+                self.next(n.unwrap_or(1), out)?
+            }
+            (Some(":tree"), None) => {
+                self.tree = !self.tree;
+                writeln!(out, "tree {}", if self.tree { "on" } else { "off" })
+                    .map_err(|e| e.to_string())?
+            }
+            (Some(":dot"), path) => {
+                self.dot = path.map(str::to_string);
+                match &self.dot {
+                    Some(path) => writeln!(out, "dot output to {path}"),
+                    None => writeln!(out, "dot off"),
+                }
+                .map_err(|e| e.to_string())?
+            }
+            (Some(":help"), None) => out.write_all(HELP.as_bytes()).map_err(|e| e.to_string())?,
+            _ => return Err(format!("unknown command {line}, see :help")),
+        }
+        Ok(true)
+    }
+}
+
+const HELP: &str = "\
+(define (name args ...) goal ...)  define a relation
+(run n (q ...) goal ...)           show the first n answers
+(run* (q ...) goal ...)            show all answers
+:next [n]                          show the next n answers of the last run
+:tree                              toggle printing the goal tree after each run
+:dot [file]                        write the goal graph to file after each run, no file disables
+:quit                              exit
+
+goals: (== a b) (=/= a b) (numbero a) (symbolo a) (fresh (x ...) goal ...)
+       (conde (goal ...) ...) (conj goal ...) (disj goal ...) (relation args ...)
+terms: 1 \"string\" x 'symbol '(1 2 . 3) `(1 ,x) (cons a b) (list a ...)
+";
+
+fn main() {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut repl = Repl::default();
+    let mut buffer = String::new();
+
+    loop {
+        print!("{}", if buffer.is_empty() { "> " } else { "  " });
+        stdout.flush().expect("flush failed");
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).expect("read failed") == 0 {
+            break;
+        }
+
+        if buffer.is_empty() && line.trim_start().starts_with(':') {
+            match repl.command(line.trim(), &mut stdout) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(e) => println!("error: {e}"),
+            }
+            continue;
+        }
+
+        buffer.push_str(&line);
+        if depth(&buffer) > 0 {
+            continue;
+        }
+
+        let input = std::mem::take(&mut buffer);
+        match read_all(&input) {
+            Ok(sexps) => {
+                for sexp in sexps {
+                    if let Err(e) = repl.eval(&sexp, &mut stdout) {
+                        println!("error: {e}");
+                        break;
+                    }
+                }
+            }
+            Err(e) => println!("error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(repl: &mut Repl, input: &str) -> String {
+        let mut out = Vec::new();
+        for sexp in read_all(input).unwrap() {
+            if let Err(e) = repl.eval(&sexp, &mut out) {
+                writeln!(out, "error: {e}").unwrap();
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn read() {
+        assert_eq!(
+            read_all("(a . (b c)) '\"x y\" -1").unwrap(),
+            vec![
+                Sexp::List(
+                    vec![
+                        Sexp::Sym("a".to_string()),
+                        Sexp::Sym("b".to_string()),
+                        Sexp::Sym("c".to_string())
+                    ],
+                    None
+                ),
+                Sexp::wrap("quote", Sexp::Str("x y".to_string())),
+                Sexp::Int(-1),
+            ]
+        );
+        assert_eq!(depth("(a (b \")\" ; )\n"), 2);
+    }
+
+    #[test]
+    fn run_relation() {
+        let mut repl = Repl::default();
+        let appendo = "
+            (define (appendo a b ab)
+              (conde
+                ((== a '()) (== ab b))
+                ((fresh (h t r)
+                   (== a `(,h . ,t))
+                   (== ab `(,h . ,r))
+                   (appendo t b r)))))";
+        assert_eq!(eval(&mut repl, appendo), "");
+        assert_eq!(
+            eval(&mut repl, "(run* (x y) (appendo x y '(1 2)))"),
+            "(() (1 2))\n((1) (2))\n((1 2) ())\n"
+        );
+
+        assert_eq!(eval(&mut repl, "(run 1 (q) (appendo '(1) q '(1 2)))"), "((2))\n");
+        let mut out = Vec::new();
+        repl.command(":next", &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "no more answers\n");
+    }
+
+    #[test]
+    fn constraints() {
+        let mut repl = Repl::default();
+        assert_eq!(
+            eval(&mut repl, "(run* (q) (=/= q 6))"),
+            "(_0) : (((_0 . 6)))\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut repl = Repl::default();
+        assert_eq!(
+            eval(&mut repl, "(run* (q) (== q x))"),
+            "error: unbound variable x, use 'x for a symbol\n"
+        );
+        assert_eq!(
+            eval(&mut repl, "(run* (q) (foo q))"),
+            "error: unknown relation foo\n"
+        );
+    }
+
+    #[test]
+    fn redefine_while_paused() {
+        let mut repl = Repl::default();
+        let nat = "(define (nat n) (conde ((== n '())) ((fresh (m) (== n `(s . ,m)) (nat m)))))";
+        assert_eq!(eval(&mut repl, nat), "");
+        assert_eq!(eval(&mut repl, "(run 1 (q) (nat q))"), "(())\n");
+        assert_eq!(eval(&mut repl, "(define (nat n m) (== n m))"), "");
+
+        let mut out = Vec::new();
+        let error = repl.command(":next", &mut out).unwrap_err();
+        assert_eq!(error, "nat expects 2 arguments, got 1");
+        assert_eq!(repl.command(":next", &mut out).unwrap_err(), "no active query");
+    }
+}
@@ -0,0 +1,258 @@
+// Native finite sets, `Term::Set(elements, rest)`
+//
+// `rest` is `()` for a closed set, or a variable standing for the other elements of an open set,
+// displayed as `{1 2 | _0}`. Resolved sets are normalized: nested rests are flattened into one
+// set and the elements are sorted without duplicates, so equal ground sets are equal terms.
+//
+// Unifying two sets that aren't both ground has several most general unifiers. `Unify` records
+// these equations and the `Eq` goal expands them into a disjunction with `unify`, following the
+// set unification rules of Dovier et al., "Sets and constraint logic programming" (2000).
+// Disequality of sets waits until both sides are ground, as the goal `neq` in answers.
+
+use crate::*;
+
+impl Term {
+    /// Closed set of `elements`
+    pub fn set<T: Into<Term>>(elements: impl IntoIterator<Item = T>) -> Term {
+        Term::set_with(elements, NULL)
+    }
+
+    /// Set of `elements` and the elements of the set `rest`, usually a variable
+    pub fn set_with<T: Into<Term>>(
+        elements: impl IntoIterator<Item = T>,
+        rest: impl Into<Term>,
+    ) -> Term {
+        Term::Set(
+            Rc::new(elements.into_iter().map(Into::into).collect()),
+            Rc::new(rest.into()),
+        )
+    }
+}
+
+/// Flatten and sort a set whose elements and rest are already resolved
+pub(crate) fn normalize(mut elements: Vec<Term>, rest: Term) -> Term {
+    let rest = match rest {
+        Term::Set(more, rest) => {
+            elements.extend(more.iter().cloned());
+            rest.as_ref().clone()
+        }
+        rest => rest,
+    };
+    elements.sort();
+    elements.dedup();
+    Term::Set(Rc::new(elements), Rc::new(rest))
+}
+
+fn parts(set: &Term) -> (&[Term], &Term) {
+    match set {
+        Term::Set(elements, rest) => (elements, rest),
+        _ => unreachable!("not a set"),
+    }
+}
+
+/// Rest of a set as a set term
+fn rest_term(rest: &Term) -> Term {
+    match rest {
+        Term::Null => Term::set::<Term>([]),
+        rest => rest.clone(),
+    }
+}
+
+fn with_rest(elements: &[Term], rest: impl Into<Term>) -> Term {
+    Term::set_with(elements.iter().cloned(), rest)
+}
+
+/// Goal unifying two resolved sets
+pub(crate) fn unify(l: &Term, r: &Term) -> Goal {
+    let (a, x) = parts(l);
+    let (b, y) = parts(r);
+
+    match (a, b) {
+        ([], []) => eq(rest_term(x), rest_term(y)),
+        ([], _) if *x == NULL => fail(),
+        (_, []) if *y == NULL => fail(),
+        ([], _) if x == y => {
+            let (b, x) = (b.to_vec(), x.clone());
+            fresh(move |n| eq(&x, with_rest(&b, n)))
+        }
+        (_, []) if x == y => unify(r, l),
+        ([], _) => eq(x, r),
+        (_, []) => eq(y, l),
+        ([a0, a_rest @ ..], _) if x == y && *x != NULL => {
+            // {a0 ..a_rest | x} = {..b | x}, a0 is one of b or is in x
+            let s = with_rest(a_rest, x);
+            let mut alternatives = Vec::new();
+            for j in 0..b.len() {
+                let mut others = b.to_vec();
+                let bj = others.remove(j);
+                let t = with_rest(&others, x);
+                // a0 occurs again among the other elements of either side. A duplicate in `x` is
+                // already covered by `s = t`, so these are only needed when there are other
+                // elements, and once when both sides have the same ones.
+                if !a_rest.is_empty() {
+                    alternatives.push(all([eq(a0, &bj), eq(&s, r)]));
+                }
+                if !others.is_empty() && a_rest != others.as_slice() {
+                    alternatives.push(all([eq(a0, &bj), eq(l, &t)]));
+                }
+                alternatives.push(all([eq(a0, &bj), eq(&s, &t)]));
+            }
+            let (a0, a_rest, b, x) = (a0.clone(), a_rest.to_vec(), b.to_vec(), x.clone());
+            alternatives.push(fresh(move |n| {
+                all([
+                    eq(&x, Term::set_with([&a0], n)),
+                    eq(with_rest(&a_rest, n), with_rest(&b, n)),
+                ])
+            }));
+            any(alternatives)
+        }
+        ([a0, a_rest @ ..], [b0, b_rest @ ..]) => {
+            let s = with_rest(a_rest, x);
+            let t = with_rest(b_rest, y);
+            let (a0, b0) = (a0.clone(), b0.clone());
+            any([
+                all([eq(&a0, &b0), eq(&s, &t)]),
+                all([eq(&a0, &b0), eq(l, &t)]),
+                all([eq(&a0, &b0), eq(&s, r)]),
+                fresh(move |n| {
+                    all([
+                        eq(&s, Term::set_with([&b0], n)),
+                        eq(Term::set_with([&a0], n), &t),
+                    ])
+                }),
+            ])
+        }
+    }
+}
+
+/// `x` is an element of the set `set`
+pub fn member(x: impl Into<Term>, set: impl Into<Term>) -> Goal {
+    let x = x.into();
+    project([set.into()], move |terms| match &terms[0] {
+        Term::Set(elements, rest) => {
+            let mut alternatives: Vec<Goal> = elements.iter().map(|e| eq(&x, e)).collect();
+            if let Term::Var(_, _) = rest.as_ref() {
+                let (x, rest) = (x.clone(), rest.clone());
+                alternatives.push(fresh(move |n| eq(rest.as_ref(), Term::set_with([&x], n))));
+            }
+            Some(if alternatives.is_empty() {
+                fail()
+            } else {
+                any(alternatives)
+            })
+        }
+        Term::Var(_, _) => None,
+        _ => Some(fail()),
+    })
+}
+
+/// `x` isn't an element of the set `set`
+pub fn not_member(x: impl Into<Term>, set: impl Into<Term>) -> Goal {
+    let x = x.into();
+    project([set.into()], move |terms| match &terms[0] {
+        Term::Set(elements, rest) => {
+            let mut goals: Vec<Goal> = elements.iter().map(|e| neq(&x, e)).collect();
+            if let Term::Var(_, _) = rest.as_ref() {
+                goals.push(not_member(&x, rest.as_ref()));
+            }
+            Some(if goals.is_empty() {
+                succeed()
+            } else {
+                all(goals)
+            })
+        }
+        Term::Var(_, _) => None,
+        _ => Some(fail()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    fn answers<const N: usize>(result: Vec<StateN<N>>) -> String {
+        let result: Vec<_> = result.iter().map(StateN::canonical).collect();
+        AsScheme(result).to_string()
+    }
+
+    #[test]
+    fn ground() {
+        let result = run_all(|| eq(Term::set([1, 2, 2]), Term::set([2, 1])));
+        assert_eq!(result.len(), 1);
+
+        let result = run_all(|| eq(Term::set([1, 2]), Term::set([1, 3])));
+        assert_eq!(result.len(), 0);
+
+        let result = run_all(|q| eq(q, Term::set([3, 1, 2, 1])));
+        assert_eq!(answers(result), "(({1 2 3}))");
+    }
+
+    #[test]
+    fn open() {
+        let result = run_all(|x| eq(Term::set_with([1], x), Term::set([1, 2])));
+        assert_eq!(answers(result), "(({2}) ({1 2}))");
+
+        let result = run_all(|x, y| eq(Term::set([x, y]), Term::set([1, 2])));
+        assert_eq!(answers(result), "((1 2) (2 1))");
+
+        let result = run_all(|x| eq(Term::set([x]), Term::set([1, 2])));
+        assert_eq!(answers(result), "()");
+
+        let result = run_all(|x, y| eq(Term::set_with([1], x), Term::set_with([2], y)));
+        assert_eq!(answers(result), "(({2 | _0} {1 | _0}))");
+
+        let result = run_all(|a, x| eq(Term::set_with([a], x), Term::set_with([1], x)));
+        assert_eq!(answers(result), "((1 _0) (_0 {1 _0 | _1}))");
+
+        // The duplicate among the elements is still found
+        let result = run_all(|a, b, x| {
+            all([
+                eq(Term::set_with([a, b], x), Term::set_with([1], x)),
+                eq(a, 1),
+                eq(b, 1),
+            ])
+        });
+        assert!(answers(result).starts_with("((1 1 _0) "));
+    }
+
+    #[test]
+    fn membership() {
+        let result = run_all(|x| member(x, Term::set([1, 2, 3])));
+        assert_eq!(answers(result), "((1) (2) (3))");
+
+        let result = run_all(|s| member(2, Term::set_with([1], s)));
+        assert_eq!(answers(result), "(({2 | _0}))");
+
+        let result = run_all(|x| {
+            all([
+                not_member(x, Term::set([1, 2])),
+                member(x, Term::set([2, 3])),
+            ])
+        });
+        assert_eq!(answers(result), "((3))");
+
+        let result = run_all(|s| all([not_member(1, s), eq(s, Term::set([2, 1]))]));
+        assert_eq!(answers(result), "()");
+    }
+
+    #[test]
+    fn disequality() {
+        let result = run_all(|x| {
+            all([
+                neq(Term::set_with([x], Term::set([1])), Term::set([1, 2])),
+                any([eq(x, 1), eq(x, 2)]),
+            ])
+        });
+        assert_eq!(answers(result), "((1))");
+
+        let result = run_all(|x| neq(Term::set([x]), Term::set([1])));
+        assert_eq!(answers(result), "((_0) : ((neq {_0} {1})))");
+
+        // Reachable through the set
+        let result = run_all(|q| {
+            fresh(move |x| all([eq(q, Term::set([Term::from(x), 5.into()])), neq(x, 1)]))
+        });
+        assert_eq!(answers(result), "(({5 _0}) : (((_0 . 1))))");
+    }
+}
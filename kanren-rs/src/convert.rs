@@ -0,0 +1,516 @@
+use crate::{cons, Term, NULL};
+
+use std::fmt::Display;
+
+/// Convert a reified term into Rust data
+pub trait FromTerm: Sized {
+    fn from_term(term: &Term) -> Result<Self, FromTermError>;
+}
+
+/// Convert Rust data into a term
+pub trait IntoTerm {
+    fn into_term(self) -> Term;
+}
+
+/// Convert Rust data into a term, for values that might not have one
+pub trait TryIntoTerm {
+    fn try_into_term(self) -> Result<Term, IntoTermError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntoTermError {
+    /// The number doesn't fit in a term number, an `i32`
+    OutOfRange { from: &'static str, found: String },
+}
+
+impl Display for IntoTermError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfRange { from, found } => {
+                write!(f, "{found} of type {from} doesn't fit in a term number")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntoTermError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FromTermError {
+    /// The term is still an unbound variable
+    Unbound(Term),
+    /// The term doesn't have the expected shape
+    Mismatch { expected: &'static str, found: Term },
+    /// The number doesn't fit in the target type
+    OutOfRange { expected: &'static str, found: i32 },
+}
+
+impl FromTermError {
+    pub fn mismatch(expected: &'static str, found: &Term) -> Self {
+        match found {
+            Term::Var(_, _) | Term::Type(_) => Self::Unbound(found.clone()),
+            _ => Self::Mismatch {
+                expected,
+                found: found.clone(),
+            },
+        }
+    }
+}
+
+impl Display for FromTermError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::display::AsScheme;
+        match self {
+            Self::Unbound(term) => write!(f, "unbound term {}", AsScheme(term)),
+            Self::Mismatch { expected, found } => {
+                write!(f, "expected {expected}, found {}", AsScheme(found))
+            }
+            Self::OutOfRange { expected, found } => write!(f, "{found} doesn't fit in {expected}"),
+        }
+    }
+}
+
+impl std::error::Error for FromTermError {}
+
+/// Encode data as a tagged list `(tag fields ...)`, or just `tag` without fields.
+/// This is the encoding used by the derive macros.
+pub fn tagged_term(tag: &str, fields: Vec<Term>) -> Term {
+    if fields.is_empty() {
+        Term::from(tag)
+    } else {
+        cons(tag, list_term(fields))
+    }
+}
+
+/// Tag of a term encoded with `tagged_term`
+pub fn tag_of(term: &Term) -> Option<&str> {
+    match term {
+        Term::String(tag) => Some(tag),
+        Term::Cons(head, _) => match head.as_ref() {
+            Term::String(tag) => Some(tag),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Fields of a term encoded with `tagged_term`, checking the tag and the number of fields
+pub fn tagged_fields(
+    term: &Term,
+    tag: &str,
+    arity: usize,
+    expected: &'static str,
+) -> Result<Vec<Term>, FromTermError> {
+    let mismatch = || FromTermError::mismatch(expected, term);
+    if tag_of(term) != Some(tag) {
+        return Err(mismatch());
+    }
+
+    match term {
+        Term::String(_) if arity == 0 => Ok(Vec::new()),
+        Term::Cons(_, tail) if arity > 0 => {
+            let fields = tail.to_vec().ok_or_else(mismatch)?;
+            if fields.len() == arity {
+                Ok(fields)
+            } else {
+                Err(mismatch())
+            }
+        }
+        _ => Err(mismatch()),
+    }
+}
+
+fn list_term(items: Vec<Term>) -> Term {
+    items.into_iter().rev().fold(NULL, |tail, item| cons(item, tail))
+}
+
+impl FromTerm for Term {
+    fn from_term(term: &Term) -> Result<Self, FromTermError> {
+        Ok(term.clone())
+    }
+}
+
+impl IntoTerm for Term {
+    fn into_term(self) -> Term {
+        self
+    }
+}
+
+impl FromTerm for i32 {
+    fn from_term(term: &Term) -> Result<Self, FromTermError> {
+        match term {
+            Term::Value(i) => Ok(*i),
+            _ => Err(FromTermError::mismatch("number", term)),
+        }
+    }
+}
+
+macro_rules! integer {
+    ($($t:ty),+) => {$(
+        impl FromTerm for $t {
+            fn from_term(term: &Term) -> Result<Self, FromTermError> {
+                let i = i32::from_term(term)?;
+                i.try_into().map_err(|_| FromTermError::OutOfRange {
+                    expected: stringify!($t),
+                    found: i,
+                })
+            }
+        }
+    )+};
+}
+
+integer!(i8, i16, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+/// Integers that always fit in a term number
+macro_rules! into_integer {
+    ($($t:ty),+) => {$(
+        impl IntoTerm for $t {
+            fn into_term(self) -> Term {
+                Term::Value(self.into())
+            }
+        }
+    )+};
+}
+
+into_integer!(i8, i16, i32, u8, u16);
+
+/// Wider integers, converted when they fit in a term number
+macro_rules! try_into_integer {
+    ($($t:ty),+) => {$(
+        impl TryIntoTerm for $t {
+            fn try_into_term(self) -> Result<Term, IntoTermError> {
+                i32::try_from(self)
+                    .map(Term::Value)
+                    .map_err(|_| IntoTermError::OutOfRange {
+                        from: stringify!($t),
+                        found: self.to_string(),
+                    })
+            }
+        }
+    )+};
+}
+
+try_into_integer!(i64, i128, isize, u32, u64, u128, usize);
+
+impl FromTerm for String {
+    fn from_term(term: &Term) -> Result<Self, FromTermError> {
+        match term {
+            Term::String(s) => Ok(s.clone()),
+            _ => Err(FromTermError::mismatch("string", term)),
+        }
+    }
+}
+
+impl IntoTerm for String {
+    fn into_term(self) -> Term {
+        Term::String(self)
+    }
+}
+
+impl IntoTerm for &str {
+    fn into_term(self) -> Term {
+        Term::from(self)
+    }
+}
+
+/// Booleans are the atoms `#t` and `#f`
+impl FromTerm for bool {
+    fn from_term(term: &Term) -> Result<Self, FromTermError> {
+        match term {
+            Term::String(s) if s == "#t" => Ok(true),
+            Term::String(s) if s == "#f" => Ok(false),
+            _ => Err(FromTermError::mismatch("#t or #f", term)),
+        }
+    }
+}
+
+impl IntoTerm for bool {
+    fn into_term(self) -> Term {
+        Term::from(if self { "#t" } else { "#f" })
+    }
+}
+
+impl<T: FromTerm> FromTerm for Vec<T> {
+    fn from_term(term: &Term) -> Result<Self, FromTermError> {
+        let mut result = Vec::new();
+        let mut rest = term;
+        loop {
+            match rest {
+                Term::Null => return Ok(result),
+                Term::Cons(head, tail) => {
+                    result.push(T::from_term(head)?);
+                    rest = tail;
+                }
+                _ => return Err(FromTermError::mismatch("list", rest)),
+            }
+        }
+    }
+}
+
+impl<T: IntoTerm> IntoTerm for Vec<T> {
+    fn into_term(self) -> Term {
+        list_term(self.into_iter().map(T::into_term).collect())
+    }
+}
+
+/// Options are lists of at most one element, `()` or `(x)`, which no string or number is
+impl<T: FromTerm> FromTerm for Option<T> {
+    fn from_term(term: &Term) -> Result<Self, FromTermError> {
+        match term {
+            Term::Null => Ok(None),
+            Term::Cons(x, tail) if **tail == NULL => T::from_term(x).map(Some),
+            _ => Err(FromTermError::mismatch("option", term)),
+        }
+    }
+}
+
+impl<T: IntoTerm> IntoTerm for Option<T> {
+    fn into_term(self) -> Term {
+        match self {
+            None => NULL,
+            Some(x) => list_term(vec![x.into_term()]),
+        }
+    }
+}
+
+/// Tuples are lists of fixed length
+macro_rules! tuple {
+    ($len:expr => $($t:ident $i:tt),*) => {
+        impl<$($t: FromTerm),*> FromTerm for ($($t,)*) {
+            fn from_term(term: &Term) -> Result<Self, FromTermError> {
+                let mismatch = || FromTermError::mismatch(concat!("list of length ", $len), term);
+                let items = term.to_vec().ok_or_else(mismatch)?;
+                if items.len() != $len {
+                    return Err(mismatch());
+                }
+                #[allow(unused_variables, unused_mut)]
+                let mut items = items.iter();
+                Ok(($($t::from_term(items.next().unwrap())?,)*))
+            }
+        }
+
+        impl<$($t: IntoTerm),*> IntoTerm for ($($t,)*) {
+            fn into_term(self) -> Term {
+                list_term(vec![$(self.$i.into_term()),*])
+            }
+        }
+    };
+}
+
+tuple!(0 =>);
+tuple!(1 => A 0);
+tuple!(2 => A 0, B 1);
+tuple!(3 => A 0, B 1, C 2);
+tuple!(4 => A 0, B 1, C 2, D 3);
+tuple!(5 => A 0, B 1, C 2, D 3, E 4);
+tuple!(6 => A 0, B 1, C 2, D 3, E 4, F 5);
+tuple!(7 => A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple!(8 => A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+#[cfg(test)]
+mod tests {
+    use crate::convert::{FromTermError, IntoTermError, TryIntoTerm};
+    use crate::*;
+
+    #[test]
+    fn primitives() {
+        assert_eq!(i32::from_term(&Term::from(5)), Ok(5));
+        assert_eq!(
+            u8::from_term(&Term::from(-1)),
+            Err(FromTermError::OutOfRange {
+                expected: "u8",
+                found: -1
+            })
+        );
+        assert_eq!(String::from_term(&Term::from("hi")), Ok("hi".to_string()));
+        assert_eq!(u16::from_term(&7u16.into_term()), Ok(7));
+        assert_eq!((-2i8).into_term(), Term::from(-2));
+        assert_eq!(usize::from_term(&3usize.try_into_term().unwrap()), Ok(3));
+        assert_eq!((-2i64).try_into_term(), Ok(Term::from(-2)));
+        assert_eq!(bool::from_term(&true.into_term()), Ok(true));
+        assert_eq!(
+            i32::from_term(&Term::from("hi")),
+            Err(FromTermError::Mismatch {
+                expected: "number",
+                found: Term::from("hi")
+            })
+        );
+        assert_eq!(
+            i32::from_term(&Term::from(Var(3))),
+            Err(FromTermError::Unbound(Term::from(Var(3))))
+        );
+    }
+
+    #[test]
+    fn integer_range() {
+        assert_eq!((i32::MAX as u64).try_into_term(), Ok(Term::from(i32::MAX)));
+        assert_eq!((i32::MIN as i64).try_into_term(), Ok(Term::from(i32::MIN)));
+        assert_eq!(
+            (i32::MAX as u64 + 1).try_into_term(),
+            Err(IntoTermError::OutOfRange {
+                from: "u64",
+                found: "2147483648".to_string()
+            })
+        );
+        assert!((i32::MIN as i64 - 1).try_into_term().is_err());
+        assert!(u64::MAX.try_into_term().is_err());
+    }
+
+    #[test]
+    fn compound() {
+        assert_eq!(Vec::<i32>::from_term(&list!(1, 2, 3)), Ok(vec![1, 2, 3]));
+        assert_eq!(
+            Vec::<i32>::from_term(&list!(1, . Var(0))),
+            Err(FromTermError::Unbound(Term::from(Var(0))))
+        );
+        assert_eq!(
+            <(i32, String)>::from_term(&list!(1, "a")),
+            Ok((1, "a".to_string()))
+        );
+        assert!(<(i32, String)>::from_term(&list!(1, "a", 2)).is_err());
+        assert_eq!(<()>::from_term(&NULL), Ok(()));
+
+        for x in [None, Some(3)] {
+            assert_eq!(Option::<i32>::from_term(&x.into_term()), Ok(x));
+        }
+        assert_eq!(Some(vec![1, 2]).into_term(), list!(list!(1, 2)));
+        for x in [None, Some(None), Some(Some("None".to_string()))] {
+            assert_eq!(Option::<Option<String>>::from_term(&x.clone().into_term()), Ok(x));
+        }
+        assert!(Option::<String>::from_term(&Term::from("None")).is_err());
+    }
+
+    #[test]
+    fn derive() {
+        #[derive(Debug, PartialEq, FromTerm, IntoTerm)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        #[derive(Debug, PartialEq, FromTerm, IntoTerm)]
+        struct Wrapper<T>(T, String);
+
+        #[derive(Debug, PartialEq, FromTerm, IntoTerm)]
+        enum Shape {
+            Empty,
+            Circle(Point, u8),
+            Rect { corners: Vec<Point> },
+        }
+
+        let p = Point { x: 1, y: 2 };
+        assert_eq!(p.into_term(), list!("Point", 1, 2));
+        assert_eq!(Point::from_term(&list!("Point", 3, 4)), Ok(Point { x: 3, y: 4 }));
+        assert!(Point::from_term(&list!("Point", 3)).is_err());
+        assert!(Point::from_term(&list!("Other", 3, 4)).is_err());
+
+        let w = Wrapper(5u8, "five".to_string());
+        assert_eq!(w.into_term(), list!("Wrapper", 5, "five"));
+
+        for shape in [
+            Shape::Empty,
+            Shape::Circle(Point { x: 0, y: 0 }, 4),
+            Shape::Rect {
+                corners: vec![Point { x: 0, y: 0 }, Point { x: 2, y: 1 }],
+            },
+        ] {
+            let term = Term::from(shape);
+            assert_eq!(Shape::from_term(&term).map(Term::from), Ok(term));
+        }
+        assert_eq!(Term::from(Shape::Empty), Term::from("Empty"));
+        assert_eq!(
+            Shape::from_term(&Term::from("Square")),
+            Err(FromTermError::Mismatch {
+                expected: "Shape",
+                found: Term::from("Square")
+            })
+        );
+    }
+
+    #[test]
+    fn derive_term() {
+        #[derive(Debug, PartialEq, Term)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        #[derive(Debug, PartialEq, Term)]
+        enum Shape {
+            Dot(Point),
+            LineSegment(Point, Point),
+        }
+
+        #[derive(Debug, PartialEq, Term)]
+        enum Message {
+            HTTPRequest(String),
+            XMLHttpRequest(String),
+            IO,
+        }
+
+        assert_eq!(Point::pattern(1, Var(0)), list!("Point", 1, Var(0)));
+        assert_eq!(
+            Shape::line_segment_pattern(Var(0), Point { x: 1, y: 2 }),
+            list!("LineSegment", Var(0), list!("Point", 1, 2))
+        );
+
+        // A run of capitals is one word
+        assert_eq!(
+            Message::http_request_pattern("GET"),
+            list!("HTTPRequest", "GET")
+        );
+        assert_eq!(
+            Message::xml_http_request_pattern(Var(0)),
+            list!("XMLHttpRequest", Var(0))
+        );
+        assert_eq!(Message::io_pattern(), Term::from("IO"));
+
+        let shapes = || {
+            list!(
+                Shape::Dot(Point { x: 0, y: 0 }),
+                Shape::LineSegment(Point { x: 0, y: 1 }, Point { x: 2, y: 3 }),
+                Shape::Dot(Point { x: 4, y: 5 }),
+            )
+        };
+
+        // Find all points with x = 0
+        let result = run_all(|p| {
+            fresh(move |y, shape, dot| {
+                all([
+                    list::contains(shapes(), shape),
+                    eq(p, Point::pattern(0, y)),
+                    any([
+                        eq(shape, Shape::dot_pattern(p)),
+                        eq(shape, Shape::line_segment_pattern(p, dot)),
+                    ]),
+                ])
+            })
+        });
+        let points: Vec<Point> = result
+            .iter()
+            .map(|s| {
+                let [p] = s.reify_as().unwrap();
+                p
+            })
+            .collect();
+        assert_eq!(points, vec![Point { x: 0, y: 0 }, Point { x: 0, y: 1 }]);
+    }
+
+    #[test]
+    fn reify_as() {
+        let result = run_all(|x| eq(x, 3));
+        assert_eq!(result[0].reify_as::<i32>(), Ok([3]));
+
+        let result = run_all(|x, y| all([eq(x, 1), eq(y, 2)]));
+        assert_eq!(result[0].reify_as::<i32>(), Ok([1, 2]));
+
+        // Different types, converted one by one
+        let result = run_all(|xs, s| all([eq(xs, list!(1, 2)), eq(s, "hi")]));
+        let [xs, s] = result[0].reify_as::<Term>().unwrap();
+        assert_eq!(Vec::<i32>::from_term(&xs), Ok(vec![1, 2]));
+        assert_eq!(String::from_term(&s), Ok("hi".to_string()));
+
+        let result = run_all(|x| fresh(move |tail| eq(x, cons(1, tail))));
+        assert!(result[0].reify_as::<Vec<i32>>().is_err());
+    }
+}
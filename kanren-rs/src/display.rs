@@ -1,8 +1,9 @@
-use crate::{purify, reify, FreshInner, TermType, YieldInner};
-use crate::{Goal, StateN, Term, Var};
+use crate::{canonical, purify, reify, residual, FreshInner, TermType, YieldInner};
+use crate::{Bindings, Canonical, Constraints, DelayedForm, Goal, StateN, Term, Var};
 
 use std::fmt::Display;
 use std::ops::Deref;
+use std::rc::Rc;
 
 pub struct AsScheme<T: DisplayScheme>(pub T);
 
@@ -28,16 +29,74 @@ impl<const N: usize> DisplayScheme for StateN<N> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         AsScheme(reify::<N>(&self.state)).fmt(f)?;
         let mut constraints = purify::<N>(&self.state);
-        if !constraints.is_empty() {
-            // Sort to make string representation comparable
-            for constraint in constraints.iter_mut() {
-                constraint.sort();
+        // Sort to make string representation comparable
+        for constraint in constraints.iter_mut() {
+            constraint.sort();
+        }
+        constraints.sort();
+        write_residual(&constraints, &residual::<N>(&self.state), f)
+    }
+}
+
+impl<const N: usize> DisplayScheme for Canonical<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Scheme(&self.terms).fmt(f)?;
+        write_residual(&self.constraints, &self.delayed, f)
+    }
+}
+
+/// Constraints and then the goals still delayed, like `(((_0 . 6)) (project _1))`
+pub struct Residual<'a> {
+    pub constraints: &'a Constraints,
+    pub delayed: &'a [DelayedForm],
+}
+
+impl Residual<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty() && self.delayed.is_empty()
+    }
+}
+
+impl DisplayScheme for Residual<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("(")?;
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" ")?;
             }
-            constraints.sort();
-            f.write_str(" : ")?;
-            Scheme(&constraints).fmt(f)?;
+            Scheme(constraint).fmt(f)?;
         }
-        Ok(())
+        for (i, form) in self.delayed.iter().enumerate() {
+            if i != 0 || !self.constraints.is_empty() {
+                f.write_str(" ")?;
+            }
+            form.fmt(f)?;
+        }
+        f.write_str(")")
+    }
+}
+
+fn write_residual(
+    constraints: &Constraints,
+    delayed: &[DelayedForm],
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    let residual = Residual { constraints, delayed };
+    if !residual.is_empty() {
+        f.write_str(" : ")?;
+        residual.fmt(f)?;
+    }
+    Ok(())
+}
+
+impl DisplayScheme for DelayedForm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("({}", self.name))?;
+        for arg in &self.args {
+            f.write_str(" ")?;
+            Scheme(arg).fmt(f)?;
+        }
+        f.write_str(")")
     }
 }
 
@@ -55,48 +114,136 @@ impl DisplayScheme for Term {
 
 impl DisplayScheme for &Term {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        fn inner(term: &Term, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match term {
-                Term::Null => Ok(()),
-                Term::Cons(head, tail) => {
+        write_term(self, f, VarStyle::Plain)
+    }
+}
+
+/// How variables are written, `_3` for plain and `_.3` for reified variables, `_.3` without the
+/// type for miniKanren
+#[derive(Clone, Copy)]
+enum VarStyle {
+    Plain,
+    Reified,
+    MiniKanren,
+}
+
+fn symbol(t: TermType) -> &'static str {
+    match t {
+        TermType::Any => "_",
+        TermType::Number => "#",
+        TermType::String => "*",
+    }
+}
+
+fn write_var(v: Var, t: TermType, f: &mut std::fmt::Formatter<'_>, style: VarStyle) -> std::fmt::Result {
+    match style {
+        VarStyle::Plain => f.write_fmt(format_args!("{}{}", symbol(t), v.0)),
+        VarStyle::Reified => f.write_fmt(format_args!("{}.{}", symbol(t), v.0)),
+        VarStyle::MiniKanren => f.write_fmt(format_args!("_.{}", v.0)),
+    }
+}
+
+fn write_term(term: &Term, f: &mut std::fmt::Formatter<'_>, style: VarStyle) -> std::fmt::Result {
+    fn inner(term: &Term, f: &mut std::fmt::Formatter<'_>, style: VarStyle) -> std::fmt::Result {
+        match term {
+            Term::Null => Ok(()),
+            Term::Cons(head, tail) => {
+                f.write_str(" ")?;
+                write_term(head, f, style)?;
+                inner(tail.as_ref(), f, style)
+            }
+            _ => {
+                f.write_str(" . ")?;
+                write_term(term, f, style)
+            }
+        }
+    }
+
+    match term {
+        Term::Var(x, t) => write_var(*x, *t, f, style),
+        Term::Value(x) => f.write_fmt(format_args!("{x}")),
+        Term::Type(t) => f.write_str(symbol(*t)),
+        Term::String(x) => {
+            if x.contains(' ') {
+                f.write_fmt(format_args!("\"{x}\""))
+            } else {
+                f.write_fmt(format_args!("{x}"))
+            }
+        }
+        Term::Null => f.write_str("()"),
+        Term::Cons(head, tail) => {
+            f.write_str("(")?;
+            write_term(head, f, style)?;
+            inner(tail.as_ref(), f, style)?;
+            f.write_str(")")
+        }
+        Term::Set(elements, rest) => {
+            f.write_str("{")?;
+            for (i, element) in elements.iter().enumerate() {
+                if i != 0 {
                     f.write_str(" ")?;
-                    head.as_ref().fmt(f)?;
-                    inner(tail.as_ref(), f)
                 }
-                _ => {
-                    f.write_str(" . ")?;
-                    term.fmt(f)
+                write_term(element, f, style)?;
+            }
+            if **rest != Term::Null {
+                f.write_str(" | ")?;
+                write_term(rest, f, style)?;
+            }
+            f.write_str("}")
+        }
+        Term::Map(entries) => {
+            f.write_str("#hash(")?;
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i != 0 {
+                    f.write_str(" ")?;
                 }
+                f.write_str("(")?;
+                write_term(key, f, style)?;
+                f.write_str(" . ")?;
+                write_term(value, f, style)?;
+                f.write_str(")")?;
             }
+            f.write_str(")")
         }
+    }
+}
 
-        fn symbol(t: TermType) -> &'static str {
-            match t {
-                TermType::Any => "_",
-                TermType::Number => "#",
-                TermType::String => "*",
+impl Display for Bindings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (name, term)) in self.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
             }
+            f.write_fmt(format_args!("{name} = "))?;
+            write_term(term, f, VarStyle::Reified)?;
         }
 
-        match self {
-            Term::Var(x, t) => f.write_fmt(format_args!("{}{}", symbol(*t), x.0)),
-            Term::Value(x) => f.write_fmt(format_args!("{x}")),
-            Term::Type(t) => f.write_str(symbol(*t)),
-            Term::String(x) => {
-                if x.contains(' ') {
-                    f.write_fmt(format_args!("\"{x}\""))
-                } else {
-                    f.write_fmt(format_args!("{x}"))
+        let (constraints, delayed) = (self.constraints(), self.delayed());
+        if !constraints.is_empty() || !delayed.is_empty() {
+            f.write_str(" : (")?;
+            for (i, constraint) in constraints.iter().enumerate() {
+                f.write_str(if i == 0 { "(" } else { " (" })?;
+                for (j, (v, t)) in constraint.iter().enumerate() {
+                    f.write_str(if j == 0 { "(" } else { " (" })?;
+                    write_var(*v, TermType::Any, f, VarStyle::Reified)?;
+                    f.write_str(" . ")?;
+                    write_term(t, f, VarStyle::Reified)?;
+                    f.write_str(")")?;
                 }
+                f.write_str(")")?;
             }
-            Term::Null => f.write_str("()"),
-            Term::Cons(head, tail) => {
-                f.write_str("(")?;
-                head.as_ref().fmt(f)?;
-                inner(tail.as_ref(), f)?;
-                f.write_str(")")
+            for (i, DelayedForm { name, args }) in delayed.iter().enumerate() {
+                let space = if i == 0 && constraints.is_empty() { "" } else { " " };
+                f.write_fmt(format_args!("{space}({name}"))?;
+                for arg in args {
+                    f.write_str(" ")?;
+                    write_term(arg, f, VarStyle::Reified)?;
+                }
+                f.write_str(")")?;
             }
+            f.write_str(")")?;
         }
+        Ok(())
     }
 }
 
@@ -125,51 +272,182 @@ impl<T: DisplayScheme> DisplayScheme for &[T] {
     }
 }
 
-pub struct GoalTree<'a>(pub &'a Goal);
+pub struct AsMiniKanren<T: DisplayMiniKanren>(pub T);
 
-impl<'a> std::fmt::Display for GoalTree<'a> {
+impl<T: DisplayMiniKanren> Display for AsMiniKanren<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        fn inner(goal: &Goal, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
-            let spacer = " ".repeat(depth);
-            match goal {
-                Goal::Eq(a, b) => f.write_fmt(format_args!("{}{:?} == {:?}\n", spacer, a, b)),
-                Goal::Neq(a, b) => f.write_fmt(format_args!("{}{:?} != {:?}\n", spacer, a, b)),
-                Goal::Both(a, b) => {
-                    f.write_str(&spacer)?;
-                    f.write_str("Both\n")?;
-                    inner(a, f, depth + 1)?;
-                    inner(b, f, depth + 1)
+        self.0.fmt(f)
+    }
+}
+
+/// Answers the way miniKanren reifies them, like `(_.0 (=/= ((_.0 6))) (num _.1))`. Constraints
+/// are grouped by kind, disequalities first, then `sym` and `num` for typed variables and the
+/// named goals still delayed like `absento`, each group sorted.
+pub trait DisplayMiniKanren {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
+}
+
+struct MiniKanrenTerm<'a>(&'a Term);
+
+impl Display for MiniKanrenTerm<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_term(self.0, f, VarStyle::MiniKanren)
+    }
+}
+
+impl<const N: usize> DisplayMiniKanren for StateN<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn types(term: &Term, sym: &mut Vec<Var>, num: &mut Vec<Var>) {
+            match term {
+                Term::Var(v, TermType::String) => sym.push(*v),
+                Term::Var(v, TermType::Number) => num.push(*v),
+                Term::Cons(a, b) => {
+                    types(a, sym, num);
+                    types(b, sym, num);
                 }
-                Goal::Either(a, b) => {
-                    f.write_str(&spacer)?;
-                    f.write_str("Either\n")?;
-                    inner(a, f, depth + 1)?;
-                    inner(b, f, depth + 1)
+                Term::Set(elements, rest) => {
+                    elements.iter().for_each(|e| types(e, sym, num));
+                    types(rest, sym, num);
                 }
-                Goal::Fresh(fresh_inner) => {
-                    f.write_str(&spacer)?;
-                    f.write_str("Fresh\n")?;
-                    let fresh_inner = fresh_inner.borrow();
-                    match fresh_inner.deref() {
-                        FreshInner::Pending(_) => {
-                            f.write_str(&spacer)?;
-                            f.write_str(" -\n")
-                        }
-                        FreshInner::Resolved(goal) => inner(goal, f, depth + 1),
-                    }
+                Term::Map(entries) => entries.iter().for_each(|(_, v)| types(v, sym, num)),
+                _ => (),
+            }
+        }
+
+        let Canonical {
+            terms,
+            constraints,
+            delayed,
+        } = canonical::<N>(&self.state);
+
+        let (mut sym, mut num) = (Vec::new(), Vec::new());
+        let constraint_terms = constraints.iter().flatten().map(|(_, t)| t);
+        let delayed_terms = delayed.iter().flat_map(|d| d.args.iter());
+        for term in terms.iter().chain(constraint_terms).chain(delayed_terms) {
+            types(term, &mut sym, &mut num);
+        }
+        for vars in [&mut sym, &mut num] {
+            vars.sort();
+            vars.dedup();
+        }
+
+        let value = if N == 1 {
+            terms[0].clone()
+        } else {
+            terms.iter().rev().fold(Term::Null, |tail, t| crate::cons(t, tail))
+        };
+        if constraints.is_empty() && sym.is_empty() && num.is_empty() && delayed.is_empty() {
+            return write!(f, "{}", MiniKanrenTerm(&value));
+        }
+
+        write!(f, "({}", MiniKanrenTerm(&value))?;
+        if !constraints.is_empty() {
+            f.write_str(" (=/=")?;
+            for constraint in &constraints {
+                f.write_str(" (")?;
+                for (i, (v, t)) in constraint.iter().enumerate() {
+                    let v = Term::from(*v);
+                    let space = if i == 0 { "" } else { " " };
+                    write!(f, "{space}({} {})", MiniKanrenTerm(&v), MiniKanrenTerm(t))?;
                 }
-                Goal::Yield(yield_inner) => {
-                    f.write_str(&spacer)?;
-                    f.write_str("Yield\n")?;
-                    let yield_inner = yield_inner.borrow();
-                    match yield_inner.deref() {
-                        YieldInner::Pending(_) => {
-                            f.write_str(&spacer)?;
-                            f.write_str(" -\n")
-                        }
-                        YieldInner::Resolved(goal) => inner(goal, f, depth + 1),
-                    }
+                f.write_str(")")?;
+            }
+            f.write_str(")")?;
+        }
+        for (name, vars) in [("sym", &sym), ("num", &num)] {
+            if !vars.is_empty() {
+                write!(f, " ({name}")?;
+                for v in vars {
+                    write!(f, " {}", MiniKanrenTerm(&Term::from(*v)))?;
                 }
+                f.write_str(")")?;
+            }
+        }
+        for group in delayed.chunk_by(|a, b| a.name == b.name) {
+            write!(f, " ({}", group[0].name)?;
+            for DelayedForm { args, .. } in group {
+                let args: Vec<_> = args.iter().map(|t| MiniKanrenTerm(t).to_string()).collect();
+                write!(f, " ({})", args.join(" "))?;
+            }
+            f.write_str(")")?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl<T: DisplayMiniKanren> DisplayMiniKanren for Vec<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("(")?;
+        for (i, x) in self.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" ")?;
+            }
+            x.fmt(f)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Call of a relation like `append(_3, (1 2), _5)`
+pub struct RelationCall<'a>(pub &'a str, pub &'a [Term]);
+
+impl<'a> Display for RelationCall<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)?;
+        f.write_str("(")?;
+        for (i, x) in self.1.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            AsScheme(x).fmt(f)?;
+        }
+        f.write_str(")")
+    }
+}
+
+pub struct GoalTree<'a>(pub &'a Goal);
+
+/// Line of `goal` in a `GoalTree`
+pub(crate) fn goal_label(goal: &Goal) -> String {
+    match goal {
+        Goal::Eq(a, b) => format!("{:?} == {:?}", a, b),
+        Goal::Neq(a, b) => format!("{:?} != {:?}", a, b),
+        Goal::Both(_, _) => "Both".to_string(),
+        Goal::Either(_, _) => "Either".to_string(),
+        Goal::Fresh(_) => "Fresh".to_string(),
+        Goal::Yield(_) => "Yield".to_string(),
+        Goal::Project(terms, _, _) => format!("Project {:?}", terms),
+        Goal::Relation(id, args, _) => RelationCall(id.name, args).to_string(),
+        Goal::Index(index) => format!("Index {:?}", index.term()),
+    }
+}
+
+/// Sub goals of `goal` in a `GoalTree`, `None` for a `Fresh` or `Yield` not run yet
+pub(crate) fn goal_children(goal: &Goal) -> Option<Vec<Rc<Goal>>> {
+    match goal {
+        Goal::Eq(_, _) | Goal::Neq(_, _) | Goal::Project(_, _, _) => Some(Vec::new()),
+        Goal::Both(a, b) | Goal::Either(a, b) => Some(vec![a.clone(), b.clone()]),
+        Goal::Fresh(inner) => match inner.borrow().deref() {
+            FreshInner::Pending(_) => None,
+            FreshInner::Resolved(goal) => Some(vec![goal.clone()]),
+        },
+        Goal::Yield(inner) => match inner.borrow().deref() {
+            YieldInner::Pending(_) => None,
+            YieldInner::Resolved(goal) => Some(vec![goal.clone()]),
+        },
+        Goal::Relation(_, _, goal) => Some(vec![goal.clone()]),
+        Goal::Index(index) => Some(index.clauses().to_vec()),
+    }
+}
+
+impl<'a> std::fmt::Display for GoalTree<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn inner(goal: &Goal, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+            let spacer = " ".repeat(depth);
+            writeln!(f, "{}{}", spacer, goal_label(goal))?;
+            match goal_children(goal) {
+                None => writeln!(f, "{} -", spacer),
+                Some(children) => children.iter().try_for_each(|x| inner(x, f, depth + 1)),
             }
         }
 
@@ -180,12 +458,49 @@ impl<'a> std::fmt::Display for GoalTree<'a> {
 
 use std::io::Write;
 
+use crate::trace::StateCounter;
+use crate::Query;
+
 pub fn output_dot(output: &mut impl Write, goal: &Goal) -> std::io::Result<()> {
+    output_dot_with(output, goal, None)
+}
+
+/// Like `output_dot`, with goals coloured by the states they returned, from white for none to
+/// red for the most. These are states counted by `StateCounter`, not answers, so a goal that
+/// returns many states the rest of the query rejects still shows hot.
+pub fn output_dot_heat(
+    output: &mut impl Write,
+    goal: &Goal,
+    counter: &StateCounter,
+) -> std::io::Result<()> {
+    output_dot_with(output, goal, Some(counter))
+}
+
+fn output_dot_with(
+    output: &mut impl Write,
+    goal: &Goal,
+    counter: Option<&StateCounter>,
+) -> std::io::Result<()> {
 
     fn id(goal: &Goal) -> usize {
         goal as *const _ as usize
     }
 
+    fn escape(s: impl Display) -> String {
+        s.to_string().escape_debug().collect()
+    }
+
+    fn node(output: &mut impl Write, goal: &Goal, label: &str, attrs: &str, counter: Option<&StateCounter>) -> std::io::Result<()> {
+        output.write_fmt(format_args!("n{} [label=\"{}\"{}", id(goal), escape(label), attrs))?;
+        if let Some(counter) = counter {
+            let states = counter.states(goal);
+            // Logarithmic, so a few states still show next to thousands
+            let heat = (states as f64).ln_1p() / (counter.max() as f64).ln_1p().max(1.0);
+            output.write_fmt(format_args!(" style=filled fillcolor=\"0.0 {:.3} 1.0\" tooltip=\"{} states\"", heat, states))?;
+        }
+        output.write_all("]\n".as_bytes())
+    }
+
     fn link(output: &mut impl Write, parent: &Goal, goal: &Goal) -> std::io::Result<()> {
         if id(parent) != id(goal) {
             output.write_fmt(format_args!("n{} -> n{}\n", id(parent), id(goal)))?;
@@ -193,80 +508,132 @@ pub fn output_dot(output: &mut impl Write, goal: &Goal) -> std::io::Result<()> {
         Ok(())
     }
 
-    fn inner(output: &mut impl Write, parent: &Goal, goal: &Goal) -> std::io::Result<()> {
+    fn terms(output: &mut impl Write, goal: &Goal, a: &Term, b: &Term) -> std::io::Result<()> {
+        output.write_fmt(format_args!("n{}_a [label=\"{}\" shape=box]\n", id(goal), escape(AsScheme(a))))?;
+        output.write_fmt(format_args!("n{} -> n{}_a\n", id(goal), id(goal)))?;
+        output.write_fmt(format_args!("n{}_b [label=\"{}\" shape=box]\n", id(goal), escape(AsScheme(b))))?;
+        output.write_fmt(format_args!("n{} -> n{}_b\n", id(goal), id(goal)))
+    }
+
+    fn inner(output: &mut impl Write, parent: &Goal, goal: &Goal, counter: Option<&StateCounter>) -> std::io::Result<()> {
         match goal {
             Goal::Eq(a, b) => {
-                output.write_fmt(format_args!("n{} [label=\"==\"]\n", id(goal)))?;
+                node(output, goal, "==", "", counter)?;
                 link(output, parent, goal)?;
-
-
-                let a = format!("{}", AsScheme(a)).escape_debug().collect::<String>();
-                let b = format!("{}", AsScheme(b)).escape_debug().collect::<String>();
-                output.write_fmt(format_args!("n{}_a [label=\"{}\" shape=box]\n", id(goal), a))?;
-                output.write_fmt(format_args!("n{} -> n{}_a\n", id(goal), id(goal)))?;
-                output.write_fmt(format_args!("n{}_b [label=\"{}\" shape=box]\n", id(goal), b))?;
-                output.write_fmt(format_args!("n{} -> n{}_b\n", id(goal), id(goal)))?;
-
-                Ok(())
+                terms(output, goal, a, b)
             },
-            Goal::Neq(_, _) => {
-                output.write_fmt(format_args!("n{} [label=\"!=\"]\n", id(goal)))?;
+            Goal::Neq(a, b) => {
+                node(output, goal, "!=", "", counter)?;
                 link(output, parent, goal)?;
-
-                Ok(())
+                terms(output, goal, a, b)
             },
             Goal::Both(a, b) => {
                 if let Goal::Both(_, _) = parent {
-                    inner(output, parent, a)?;
-                    inner(output, parent, b)?;
+                    inner(output, parent, a, counter)?;
+                    inner(output, parent, b, counter)?;
                 } else {
-                    output.write_fmt(format_args!("n{} [label=\"&&\"]\n", id(goal)))?;
+                    node(output, goal, "&&", "", counter)?;
                     link(output, parent, goal)?;
-                    inner(output, goal, a)?;
-                    inner(output, goal, b)?;
+                    inner(output, goal, a, counter)?;
+                    inner(output, goal, b, counter)?;
                 }
                 Ok(())
             },
             Goal::Either(a, b) => {
                 if let Goal::Either(_, _) = parent {
-                    inner(output, parent, a)?;
-                    inner(output, parent, b)?;
+                    inner(output, parent, a, counter)?;
+                    inner(output, parent, b, counter)?;
                 } else {
-                    output.write_fmt(format_args!("n{} [label=\"||\"]\n", id(goal)))?;
+                    node(output, goal, "||", "", counter)?;
                     link(output, parent, goal)?;
-                    inner(output, goal, a)?;
-                    inner(output, goal, b)?;
+                    inner(output, goal, a, counter)?;
+                    inner(output, goal, b, counter)?;
                 }
                 Ok(())
             },
             Goal::Fresh(i) => {
                 let i = i.borrow();
                 if let FreshInner::Resolved(x) = i.deref() {
-                    inner(output, parent, x)?;
+                    inner(output, parent, x, counter)?;
                 } else {
-                    output.write_fmt(format_args!("n{} [label=\"Fresh\"]\n", id(goal)))?;
+                    node(output, goal, "Fresh", "", counter)?;
                     link(output, parent, goal)?;
                 }
                 Ok(())
             },
             Goal::Yield(i) => {
-                let i = i.borrow();
-                if let YieldInner::Resolved(x) = i.deref() {
-                    output.write_fmt(format_args!("n{} [label=\"Yield: {}\"]\n", id(goal), std::rc::Rc::strong_count(x)))?;
-                    link(output, parent, goal)?;
-                    inner(output, goal, x)?;
-                } else {
-                    output.write_fmt(format_args!("n{} [label=\"Yield\"]\n", id(goal)))?;
-                    link(output, parent, goal)?;
+                node(output, goal, "Yield", "", counter)?;
+                link(output, parent, goal)?;
+                if let YieldInner::Resolved(x) = i.borrow().deref() {
+                    inner(output, goal, x, counter)?;
                 }
                 Ok(())
             },
+            Goal::Project(terms, _, _) => {
+                node(output, goal, &format!("project{}", AsScheme(terms.as_slice())), "", counter)?;
+                link(output, parent, goal)
+            },
+            Goal::Relation(id, args, x) => {
+                node(output, goal, &RelationCall(id.name, args).to_string(), " shape=box", counter)?;
+                link(output, parent, goal)?;
+                inner(output, goal, x, counter)
+            },
+            Goal::Index(index) => {
+                node(output, goal, &format!("index {}", AsScheme(index.term())), "", counter)?;
+                link(output, parent, goal)?;
+                index.clauses().iter().try_for_each(|x| inner(output, goal, x, counter))
+            },
         }
     }
 
-    output.write("digraph {\n".as_bytes())?;
-    inner(output, goal, goal)?;
-    output.write("}\n".as_bytes())?;
+    output.write_all("digraph {\n".as_bytes())?;
+    inner(output, goal, goal, counter)?;
+    output.write_all("}\n".as_bytes())?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Graph of the search explored by `query` so far, one node per depth with the goals called, the
+/// suspended goals resumed and the answers found there. Depths with answers are filled. Empty
+/// unless the query keeps statistics, see `query_with_stats`.
+pub fn output_search_dot<const N: usize>(output: &mut impl Write, query: &Query<N>) -> std::io::Result<()> {
+    let stats = query.stats().unwrap_or_default();
+
+    output.write_all("digraph {\nnode [shape=box]\n".as_bytes())?;
+    for (depth, d) in stats.depths.iter().enumerate() {
+        let fill = if d.answers > 0 { " style=filled fillcolor=palegreen" } else { "" };
+        output.write_fmt(format_args!(
+            "d{} [label=\"depth {}\\ngoals {}\\nresumed {}\\nanswers {}\"{}]\n",
+            depth, depth, d.goals, d.resumed, d.answers, fill
+        ))?;
+        if depth > 0 {
+            output.write_fmt(format_args!("d{} -> d{} [label=\"{}\"]\n", depth - 1, depth, d.resumed))?;
+        }
+    }
+    output.write_all("}\n".as_bytes())?;
+
+    Ok(())
+}
+
+/// Render a Graphviz file with `dot` to `format`, like `svg` or `png`
+pub fn render_dot(input: &std::path::Path, format: &str, output: &std::path::Path) -> std::io::Result<()> {
+    let status = std::process::Command::new("dot")
+        .arg(format!("-T{format}"))
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .status()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => std::io::Error::new(
+                e.kind(),
+                "dot not found, install Graphviz to render graphs",
+            ),
+            _ => e,
+        })?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!("dot failed with {status}")))
+    }
+}
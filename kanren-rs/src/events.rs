@@ -0,0 +1,409 @@
+// Event log of a query run
+//
+// `EventRecorder` is a `Tracer` writing every step of a query to a compact line based log, and
+// `Replay` reads a log back to show the goal tree as it was after any step, without the code that
+// built the goals. Attached to a bug report about a query that doesn't terminate, the log shows
+// how the search grew.
+//
+// Each line is one event, with fields separated by spaces:
+//
+//     n <id> <children> <label>   goal <id> with its sub goals comma separated, `-` for none or
+//                                 `?` for a `Fresh` or `Yield` not run yet
+//     c <id> <child>              `Fresh` or `Yield` <id> ran, giving the sub goal <child>
+//     p                           the query takes a step
+//     u <id> <depth> <states>     unification or constraint <id> gave <states>, 0 if it failed
+//     s <depth>                   a goal is suspended, continuing at <depth>
+//     r <depth>                   a suspended goal resumes
+//     a <depth>                   the query found an answer
+//
+// Goals are numbered in the order they are seen, the goal of the query is 0. Labels are the lines
+// of `GoalTree`, with backslashes and newlines escaped.
+
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+
+use crate::display::{goal_children, goal_label};
+use crate::*;
+
+/// Tracer writing the event log of a query to `out`
+pub struct EventRecorder<W: Write> {
+    out: RefCell<W>,
+    ids: RefCell<HashMap<*const Goal, usize>>,
+    pending: RefCell<HashSet<usize>>,
+    // Goals entered and not exited yet
+    stack: RefCell<Vec<Option<usize>>>,
+}
+
+impl<W: Write> EventRecorder<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: RefCell::new(out),
+            ids: RefCell::default(),
+            pending: RefCell::default(),
+            stack: RefCell::default(),
+        }
+    }
+
+    /// Output written so far
+    pub fn output(&self) -> Ref<'_, W> {
+        self.out.borrow()
+    }
+
+    fn log(&self, event: std::fmt::Arguments) {
+        // Recording is best effort, a failing writer doesn't stop the query
+        let _ = writeln!(self.out.borrow_mut(), "{event}");
+    }
+
+    fn id(&self, goal: &Goal) -> Option<usize> {
+        self.ids.borrow().get(&(goal as *const _)).copied()
+    }
+
+    // Only goals reachable from the query are numbered. They live as long as the query, so their
+    // address identifies them.
+    fn define(&self, goal: &Goal) -> usize {
+        if let Some(id) = self.id(goal) {
+            return id;
+        }
+
+        let id = {
+            let mut ids = self.ids.borrow_mut();
+            let id = ids.len();
+            ids.insert(goal, id);
+            id
+        };
+
+        let children = match goal_children(goal) {
+            None => {
+                self.pending.borrow_mut().insert(id);
+                "?".to_string()
+            }
+            Some(children) if children.is_empty() => "-".to_string(),
+            Some(children) => {
+                let ids: Vec<_> = children
+                    .iter()
+                    .map(|x| self.define(x).to_string())
+                    .collect();
+                ids.join(",")
+            }
+        };
+        self.log(format_args!(
+            "n {id} {children} {}",
+            escape(&goal_label(goal))
+        ));
+        id
+    }
+}
+
+impl<W: Write> Tracer for EventRecorder<W> {
+    fn enter(&self, goal: &Goal, _state: &State) {
+        let parent = self.stack.borrow().last().copied().flatten();
+        let id = match (self.id(goal), parent) {
+            (Some(id), _) => Some(id),
+            (None, None) if self.ids.borrow().is_empty() => Some(self.define(goal)),
+            // A `Fresh` calls its goal as soon as it has it, before its own exit
+            (None, Some(parent)) if self.pending.borrow_mut().remove(&parent) => {
+                let child = self.define(goal);
+                self.log(format_args!("c {parent} {child}"));
+                Some(child)
+            }
+            // Goals made while running, like the goal of a `project`, aren't part of the tree
+            (None, _) => None,
+        };
+        self.stack.borrow_mut().push(id);
+    }
+
+    fn exit(&self, goal: &Goal, state: &State, stream: &Stream) {
+        self.stack.borrow_mut().pop();
+        let Some(id) = self.id(goal) else { return };
+
+        // A `Yield` has its goal when it exits, and runs it later
+        if self.pending.borrow().contains(&id) {
+            if let Some([child]) = goal_children(goal).as_deref() {
+                self.pending.borrow_mut().remove(&id);
+                let child = self.define(child);
+                self.log(format_args!("c {id} {child}"));
+            }
+        }
+
+        if let Goal::Eq(_, _) | Goal::Neq(_, _) = goal {
+            let states = stream.mature.len();
+            self.log(format_args!("u {id} {} {states}", state.depth));
+        }
+    }
+
+    fn suspend(&self, state: &State) {
+        self.log(format_args!("s {}", state.depth));
+    }
+
+    fn resume(&self, state: &State) {
+        self.log(format_args!("r {}", state.depth));
+    }
+
+    fn pull(&self) {
+        self.log(format_args!("p"));
+    }
+
+    fn answer(&self, state: &State) {
+        self.log(format_args!("a {}", state.depth));
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(label: &str) -> String {
+    let mut result = String::with_capacity(label.len());
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                result.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                result.push('\\');
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Goal with its sub goals, `None` for a `Fresh` or `Yield` not run yet
+    Node {
+        id: usize,
+        children: Option<Vec<usize>>,
+        label: String,
+    },
+    /// `Fresh` or `Yield` ran, giving its sub goal
+    Resolve {
+        id: usize,
+        child: usize,
+    },
+    Pull,
+    /// Unification or constraint gave `states`, 0 if it failed
+    Unify {
+        id: usize,
+        depth: u32,
+        states: usize,
+    },
+    Suspend(u32),
+    Resume(u32),
+    Answer(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+fn parse_event(line: &str) -> Result<Event, String> {
+    fn number<T: std::str::FromStr>(field: Option<&str>) -> Result<T, String> {
+        let field = field.ok_or("missing field")?;
+        field.parse().map_err(|_| format!("bad number {field:?}"))
+    }
+
+    let mut fields = line.splitn(4, ' ');
+    let event = match fields.next() {
+        Some("n") => {
+            let id = number(fields.next())?;
+            let children = match fields.next() {
+                Some("?") => None,
+                Some("-") => Some(Vec::new()),
+                Some(children) => Some(
+                    children
+                        .split(',')
+                        .map(|child| number(Some(child)))
+                        .collect::<Result<_, _>>()?,
+                ),
+                None => return Err("missing field".to_string()),
+            };
+            let label = unescape(fields.next().unwrap_or_default());
+            return Ok(Event::Node {
+                id,
+                children,
+                label,
+            });
+        }
+        Some("c") => Event::Resolve {
+            id: number(fields.next())?,
+            child: number(fields.next())?,
+        },
+        Some("p") => Event::Pull,
+        Some("u") => Event::Unify {
+            id: number(fields.next())?,
+            depth: number(fields.next())?,
+            states: number(fields.next())?,
+        },
+        Some("s") => Event::Suspend(number(fields.next())?),
+        Some("r") => Event::Resume(number(fields.next())?),
+        Some("a") => Event::Answer(number(fields.next())?),
+        _ => return Err(format!("unknown event {line:?}")),
+    };
+
+    match fields.next() {
+        None => Ok(event),
+        Some(_) => Err(format!("extra fields in {line:?}")),
+    }
+}
+
+/// Event log read back
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Replay {
+    pub events: Vec<Event>,
+}
+
+impl Replay {
+    pub fn read(input: impl BufRead) -> Result<Self, ReplayError> {
+        let mut events = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            let error = |message| ReplayError {
+                line: i + 1,
+                message,
+            };
+            let line = line.map_err(|e| error(e.to_string()))?;
+            if !line.is_empty() {
+                events.push(parse_event(&line).map_err(error)?);
+            }
+        }
+        Ok(Self { events })
+    }
+
+    /// Steps taken by the query
+    pub fn steps(&self) -> usize {
+        self.events.iter().filter(|e| **e == Event::Pull).count()
+    }
+
+    /// Events of the first `step` steps, with 0 only the events of starting the query
+    pub fn until(&self, step: usize) -> &[Event] {
+        let end = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| **e == Event::Pull)
+            .nth(step)
+            .map_or(self.events.len(), |(i, _)| i);
+        &self.events[..end]
+    }
+
+    /// Answers found in the first `step` steps
+    pub fn answers(&self, step: usize) -> usize {
+        let answers = self.until(step).iter();
+        answers.filter(|e| matches!(e, Event::Answer(_))).count()
+    }
+
+    /// `GoalTree` of the query after `step` steps
+    pub fn tree(&self, step: usize) -> String {
+        let mut nodes = HashMap::new();
+        for event in self.until(step) {
+            match event {
+                Event::Node {
+                    id,
+                    children,
+                    label,
+                } => {
+                    nodes.insert(*id, (label.as_str(), children.clone()));
+                }
+                Event::Resolve { id, child } => {
+                    if let Some((_, children)) = nodes.get_mut(id) {
+                        *children = Some(vec![*child]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        fn inner(
+            nodes: &HashMap<usize, (&str, Option<Vec<usize>>)>,
+            id: usize,
+            depth: usize,
+            out: &mut String,
+        ) {
+            let spacer = " ".repeat(depth);
+            let Some((label, children)) = nodes.get(&id) else {
+                return;
+            };
+            out.push_str(&format!("{spacer}{label}\n"));
+            match children {
+                None => out.push_str(&format!("{spacer} -\n")),
+                Some(children) => {
+                    for child in children {
+                        inner(nodes, *child, depth + 1, out);
+                    }
+                }
+            }
+        }
+
+        let mut out = String::new();
+        inner(&nodes, 0, 0, &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::GoalTree;
+
+    fn replay(recorder: &EventRecorder<Vec<u8>>) -> Replay {
+        Replay::read(recorder.output().as_slice()).unwrap()
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let recorder = Rc::new(EventRecorder::new(Vec::new()));
+        let mut q = query_traced(recorder.clone(), |x, y| {
+            all([neq(x, list!(1)), list::append(x, y, list!(1, 2))])
+        });
+
+        let start = GoalTree(&q.goal).to_string();
+        assert_eq!(replay(&recorder).tree(0), start);
+
+        assert!(q.next().is_some());
+        let first = GoalTree(&q.goal).to_string();
+        let log = replay(&recorder);
+        let step = log.steps();
+        assert_eq!(log.tree(step), first);
+        assert_eq!(log.answers(step - 1), 0);
+        assert_eq!(log.answers(step), 1);
+
+        assert_eq!(q.iter().count(), 1);
+        let log = replay(&recorder);
+        assert_eq!(log.tree(log.steps()), GoalTree(&q.goal).to_string());
+        assert_eq!(log.tree(0), start);
+        assert_eq!(log.tree(step), first);
+
+        let unified = |n| {
+            let mut events = log.events.iter();
+            events.any(|e| matches!(e, Event::Unify { states, .. } if *states == n))
+        };
+        assert!(unified(1) && unified(0));
+        assert!(log.events.contains(&Event::Resume(1)));
+        assert_eq!(log.answers(log.steps()), 2);
+    }
+
+    #[test]
+    fn read_errors() {
+        let log = "n 0 - a \\\\ b\\nc\np\nu 0 0 1\nr x";
+        let error = Replay::read(log.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 4: bad number \"x\"");
+
+        let log = Replay::read("n 0 - a \\\\ b\\nc\n".as_bytes()).unwrap();
+        assert_eq!(log.tree(0), "a \\ b\nc\n");
+    }
+}
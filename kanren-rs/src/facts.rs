@@ -0,0 +1,268 @@
+// Fact databases
+//
+// A `FactDb` is a relation given by a table of ground rows, like thousands of rows loaded from a
+// file, instead of a `cond` of `eq` goals per row. Every column is indexed by its values. A goal
+// made by `query` looks up the rows when it runs: the column with the fewest rows for the value
+// its argument is bound to selects the candidates, which are filtered on the other bound columns
+// before the remaining arguments are unified with them. Rows come in the order they were inserted.
+//
+// Rows can be inserted and deleted between queries. Goals keep the rows as they were when the goal
+// was made, the table is copied on the next change while they exist.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::optimize::plain;
+use crate::*;
+
+#[derive(Clone, Default)]
+struct Table {
+    rows: BTreeMap<usize, Rc<[Term]>>,
+    ids: HashMap<Rc<[Term]>, usize>,
+    columns: Vec<HashMap<Term, BTreeSet<usize>>>,
+    next: usize,
+}
+
+impl Table {
+    /// Rows with the values of the arguments that are ground
+    fn select(&self, args: &[Term]) -> Vec<Rc<[Term]>> {
+        let bound: Vec<_> = (0..args.len()).filter(|i| plain(&args[*i])).collect();
+        let smallest = bound
+            .iter()
+            .map(|i| self.columns[*i].get(&args[*i]))
+            .min_by_key(|ids| ids.map_or(0, BTreeSet::len));
+
+        let candidates: Box<dyn Iterator<Item = &Rc<[Term]>>> = match smallest {
+            None => Box::new(self.rows.values()),
+            Some(None) => return Vec::new(),
+            Some(Some(ids)) => Box::new(ids.iter().map(|id| &self.rows[id])),
+        };
+        candidates
+            .filter(|row| bound.iter().all(|i| row[*i] == args[*i]))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Relation of ground rows of `arity` terms, see the module documentation
+#[derive(Clone)]
+pub struct FactDb {
+    name: &'static str,
+    arity: usize,
+    table: Rc<Table>,
+}
+
+impl FactDb {
+    pub fn new(name: &'static str, arity: usize) -> Self {
+        Self {
+            name,
+            arity,
+            table: Rc::new(Table {
+                columns: vec![HashMap::new(); arity],
+                ..Table::default()
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.rows.is_empty()
+    }
+
+    /// Rows in the order they were inserted
+    pub fn rows(&self) -> impl Iterator<Item = &[Term]> {
+        self.table.rows.values().map(|row| row.as_ref())
+    }
+
+    pub fn contains(&self, row: &[Term]) -> bool {
+        self.table.ids.contains_key(row)
+    }
+
+    fn row(&self, row: impl IntoIterator<Item = impl Into<Term>>) -> Rc<[Term]> {
+        let row: Rc<[Term]> = row.into_iter().map(Into::into).collect();
+        assert_eq!(
+            row.len(),
+            self.arity,
+            "row of {} has the wrong arity",
+            self.name
+        );
+        // Only terms equal to each other unify, so rows can be looked up by hash
+        assert!(
+            row.iter().all(plain),
+            "row of {} isn't made of numbers, strings and lists",
+            self.name
+        );
+        row
+    }
+
+    /// Add `row` unless it is already there, panics if it has the wrong arity or has terms other
+    /// than numbers, strings and lists of them
+    pub fn insert(&mut self, row: impl IntoIterator<Item = impl Into<Term>>) -> bool {
+        let row = self.row(row);
+        if self.contains(&row) {
+            return false;
+        }
+
+        let table = Rc::make_mut(&mut self.table);
+        let id = table.next;
+        table.next += 1;
+        for (column, term) in table.columns.iter_mut().zip(row.iter()) {
+            column.entry(term.clone()).or_default().insert(id);
+        }
+        table.ids.insert(row.clone(), id);
+        table.rows.insert(id, row);
+        true
+    }
+
+    /// Remove `row`, whether it was there
+    pub fn delete(&mut self, row: impl IntoIterator<Item = impl Into<Term>>) -> bool {
+        let row = self.row(row);
+        if !self.contains(&row) {
+            return false;
+        }
+
+        let table = Rc::make_mut(&mut self.table);
+        let id = table.ids.remove(&row).expect("row is there");
+        table.rows.remove(&id);
+        for (column, term) in table.columns.iter_mut().zip(row.iter()) {
+            let ids = column.get_mut(term).expect("row is indexed");
+            ids.remove(&id);
+            if ids.is_empty() {
+                column.remove(term);
+            }
+        }
+        true
+    }
+
+    /// `args` are a row of the database, panics if there are not `arity` of them
+    pub fn query(&self, args: impl IntoIterator<Item = impl Into<Term>>) -> Goal {
+        let args: Vec<Term> = args.into_iter().map(Into::into).collect();
+        assert_eq!(
+            args.len(),
+            self.arity,
+            "query of {} has the wrong arity",
+            self.name
+        );
+
+        let table = self.table.clone();
+        let goal = project(args.clone(), move |resolved| {
+            let rows = table.select(resolved);
+            let goals: Vec<_> = rows
+                .iter()
+                .map(|row| {
+                    let eqs: Vec<_> = resolved
+                        .iter()
+                        .zip(row.iter())
+                        .filter(|(arg, _)| !plain(arg))
+                        .map(|(arg, term)| eq(arg, term))
+                        .collect();
+                    if eqs.is_empty() {
+                        succeed()
+                    } else {
+                        all(eqs)
+                    }
+                })
+                .collect();
+            Some(balanced(goals))
+        });
+        let id = RelationId {
+            module: module_path!(),
+            name: self.name,
+        };
+        relation(id, args, goal)
+    }
+}
+
+// `any` nests as deep as there are goals, which overflows the stack for thousands of rows
+fn balanced(mut goals: Vec<Goal>) -> Goal {
+    match goals.len() {
+        0 => fail(),
+        1 => goals.pop().unwrap(),
+        n => {
+            let rest = goals.split_off(n / 2);
+            either(balanced(goals), balanced(rest))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    fn inventory() -> FactDb {
+        let mut db = FactDb::new("inventory", 3);
+        for i in 0..1000 {
+            let kind = ["bolt", "nut", "washer"][i as usize % 3];
+            db.insert([
+                Term::from(format!("item{i}")),
+                kind.into(),
+                list!(i % 7, i % 11),
+            ]);
+        }
+        db
+    }
+
+    #[test]
+    fn lookup() {
+        let mut db = inventory();
+        assert_eq!(db.len(), 1000);
+        assert!(!db.insert([Term::from("item3"), "bolt".into(), list!(3, 3)]));
+
+        let item = Term::from("item10");
+        let answers =
+            run_all(|kind: Var, place: Var| db.query([item.clone(), kind.into(), place.into()]));
+        assert_eq!(AsScheme(answers).to_string(), "((nut (3 10)))");
+
+        // Only the matching rows are unified
+        let mut q = query_with_stats(|item: Var| {
+            db.query([item.into(), Term::from("washer"), list!(2, 0)])
+        });
+        assert_eq!(q.iter().count(), 5);
+        assert_eq!(q.stats().unwrap().unifications, 5);
+
+        let washers =
+            run_all(|item: Var, x| db.query([item.into(), Term::from("washer"), list!(x, 0)]));
+        assert_eq!(washers.len(), 30);
+
+        let all = run_all(|item, kind, place| db.query([item, kind, place]));
+        assert_eq!(all.len(), 1000);
+        assert_eq!(
+            AsScheme(all[1].canonical()).to_string(),
+            "(item1 nut (1 1))"
+        );
+    }
+
+    #[test]
+    fn insert_and_delete() {
+        let mut db = inventory();
+        let before = db.clone();
+        let row = [Term::from("item10"), "nut".into(), list!(3, 10)];
+        assert!(db.delete(row.clone()));
+        assert!(!db.delete(row.clone()));
+        assert!(db.insert(["item10", "nut", "x"]));
+        assert_eq!(db.len(), 1000);
+        assert!(before.contains(&row) && !db.contains(&row));
+
+        let kinds = run_all(|kind: Var| db.query([Term::from("item10"), kind.into(), "x".into()]));
+        assert_eq!(AsScheme(kinds).to_string(), "((nut))");
+
+        let place = |db: &FactDb| {
+            let places =
+                run_all(|place: Var| db.query([row[0].clone(), row[1].clone(), place.into()]));
+            AsScheme(places).to_string()
+        };
+        assert_eq!(place(&db), "((x))");
+        assert_eq!(place(&before), "(((3 10)))");
+    }
+}
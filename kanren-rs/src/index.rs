@@ -0,0 +1,244 @@
+// Clause indexing
+//
+// A disjunction of clauses that each unify the same variable with a different ground term, like
+// the rows of a table of facts, can skip the clauses that can't match. `index` picks the variable
+// with the most distinct ground terms and builds a `ClauseIndex`, which looks up the clauses for
+// the value of the variable when it is called. While the variable isn't bound to a ground term all
+// clauses are tried, and clauses without a ground term for it are always tried. `any`, and so
+// `cond`, indexes a disjunction when every clause starts by unifying the same variable with a
+// different ground term, `optimize` indexes disjunctions where it can.
+
+use std::collections::HashMap;
+
+use crate::optimize::plain;
+use crate::*;
+
+pub struct ClauseIndex {
+    term: Term,
+    clauses: Vec<Rc<Goal>>,
+    keys: HashMap<Term, Vec<usize>>,
+    /// Clauses without a ground term for `term`
+    rest: Vec<usize>,
+}
+
+impl ClauseIndex {
+    /// The indexed argument
+    pub fn term(&self) -> &Term {
+        &self.term
+    }
+
+    pub fn clauses(&self) -> &[Rc<Goal>] {
+        &self.clauses
+    }
+
+    /// Clauses matching `key` and those without a key, in their original order
+    fn select(&self, key: &Term) -> Vec<usize> {
+        let keyed = self.keys.get(key).map_or(&[][..], Vec::as_slice);
+        let mut selected = Vec::with_capacity(keyed.len() + self.rest.len());
+        let (mut i, mut j) = (0, 0);
+        while i < keyed.len() || j < self.rest.len() {
+            if j == self.rest.len() || (i < keyed.len() && keyed[i] < self.rest[j]) {
+                selected.push(keyed[i]);
+                i += 1;
+            } else {
+                selected.push(self.rest[j]);
+                j += 1;
+            }
+        }
+        selected
+    }
+
+    pub(crate) fn call(&self, state: &State) -> Stream {
+        let key = deep_resolve(&self.term, &state.map);
+        let clauses = if plain(&key) {
+            state.record(|s| s.index_hits += 1);
+            self.select(&key)
+        } else {
+            (0..self.clauses.len()).collect()
+        };
+
+        clauses
+            .into_iter()
+            .map(|i| self.clauses[i].call(state))
+            .fold(Stream::default(), append)
+    }
+}
+
+/// Ground term unified with `var` by one of the first goals of `clause`
+fn key(clause: &Goal, var: Var) -> Option<&Term> {
+    match clause {
+        Goal::Eq(Term::Var(v, _), t) | Goal::Eq(t, Term::Var(v, _)) if *v == var && plain(t) => {
+            Some(t)
+        }
+        Goal::Both(a, b) => key(a, var).or_else(|| key(b, var)),
+        Goal::Relation(_, _, goal) => key(goal, var),
+        _ => None,
+    }
+}
+
+/// Variables unified with a ground term by one of the first goals of `clause`
+fn key_vars(clause: &Goal, vars: &mut Vec<Var>) {
+    match clause {
+        Goal::Eq(Term::Var(v, _), t) | Goal::Eq(t, Term::Var(v, _)) if plain(t) => vars.push(*v),
+        Goal::Both(a, b) => {
+            key_vars(a, vars);
+            key_vars(b, vars);
+        }
+        Goal::Relation(_, _, goal) => key_vars(goal, vars),
+        _ => (),
+    }
+}
+
+/// Variable and ground term unified by the first goal of `clause`
+fn head(clause: &Goal) -> Option<(Var, &Term)> {
+    match clause {
+        Goal::Eq(Term::Var(v, _), t) | Goal::Eq(t, Term::Var(v, _)) if plain(t) => Some((*v, t)),
+        Goal::Both(a, _) => head(a),
+        Goal::Relation(_, _, goal) => head(goal),
+        _ => None,
+    }
+}
+
+/// Disjunction of `clauses` indexed on the variable they all start by unifying with a ground
+/// term, or the clauses back if they don't or the terms aren't all different
+pub(crate) fn index_heads(clauses: Vec<Goal>) -> Result<Goal, Vec<Goal>> {
+    let Some(var) = clauses.first().and_then(head).map(|(var, _)| var) else {
+        return Err(clauses);
+    };
+    let mut keys: HashMap<Term, Vec<usize>> = HashMap::new();
+    for (i, clause) in clauses.iter().enumerate() {
+        match head(clause) {
+            Some((v, key)) if v == var && !keys.contains_key(key) => {
+                keys.insert(key.clone(), vec![i]);
+            }
+            _ => return Err(clauses),
+        }
+    }
+    if keys.len() < 2 {
+        return Err(clauses);
+    }
+    Ok(Goal::Index(Rc::new(ClauseIndex {
+        term: Term::from(var),
+        clauses: clauses.into_iter().map(Rc::new).collect(),
+        keys,
+        rest: Vec::new(),
+    })))
+}
+
+/// Disjunction of `clauses` indexed on the variable with the most distinct ground terms, or the
+/// clauses back if no variable has at least two
+pub fn index(clauses: Vec<Rc<Goal>>) -> Result<Goal, Vec<Rc<Goal>>> {
+    let mut vars = Vec::new();
+    for clause in &clauses {
+        key_vars(clause, &mut vars);
+    }
+    vars.sort();
+    vars.dedup();
+
+    let mut best: Option<(Var, HashMap<Term, Vec<usize>>)> = None;
+    for var in vars {
+        let mut keys: HashMap<Term, Vec<usize>> = HashMap::new();
+        for (i, clause) in clauses.iter().enumerate() {
+            if let Some(key) = key(clause, var) {
+                keys.entry(key.clone()).or_default().push(i);
+            }
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(_, best)| keys.len() > best.len())
+        {
+            best = Some((var, keys));
+        }
+    }
+
+    match best {
+        Some((var, keys)) if keys.len() >= 2 => {
+            let rest = (0..clauses.len())
+                .filter(|i| !keys.values().any(|k| k.contains(i)))
+                .collect();
+            Ok(Goal::Index(Rc::new(ClauseIndex {
+                term: Term::from(var),
+                clauses,
+                keys,
+                rest,
+            })))
+        }
+        _ => Err(clauses),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+    use crate::optimize::optimize;
+
+    fn facts(name: impl Into<Term>, parent: impl Into<Term>) -> Goal {
+        let (name, parent) = (name.into(), parent.into());
+        fresh(move |n| {
+            let (name, parent) = (&name, &parent);
+            let mut rows = vec![vec![eq(name, "adam"), eq(parent, NULL)]];
+            rows.extend(
+                (0..200)
+                    .map(|i| vec![eq(name, format!("p{i}")), eq(parent, format!("p{}", i / 2))]),
+            );
+            rows.push(vec![eq(name, list!("x", n)), eq(parent, n)]);
+            rows.push(vec![neq(name, "adam"), eq(parent, "unknown")]);
+            optimize(cond(rows))
+        })
+    }
+
+    #[test]
+    fn lookup() {
+        let mut q = query_with_stats(|p| fresh(move |name| all([eq(name, "p7"), facts(name, p)])));
+        let answers: Vec<_> = q
+            .iter()
+            .map(|s| AsScheme(s.canonical()).to_string())
+            .collect();
+        assert_eq!(answers, ["(p3)", "(unknown)"]);
+        let stats = q.stats().unwrap();
+        assert_eq!(stats.index_hits, 1);
+        // The matching row and the one without a ground name, not all 203
+        assert!(stats.unifications < 10, "{stats}");
+
+        let q = query(|p| fresh(move |name| all([eq(name, list!("x", 1)), facts(name, p)])));
+        assert_eq!(q.count(), 2);
+    }
+
+    #[test]
+    fn unbound() {
+        let answers = run_all(|name| fresh(move |p| all([facts(name, p), eq(p, "p99")])));
+        let answers = AsScheme(answers.iter().map(StateN::canonical).collect::<Vec<_>>());
+        assert_eq!(answers.to_string(), "((p198) (p199) ((x p99)))");
+    }
+
+    #[test]
+    fn cond_heads() {
+        let colour = |x: Var, c: Var| {
+            cond([
+                [eq(x, "red"), eq(c, 1)],
+                [eq(x, "green"), eq(c, 2)],
+                [eq(x, "blue"), eq(c, 3)],
+            ])
+        };
+        let mut q = query_with_stats(|c| fresh(move |x| all([eq(x, "green"), colour(x, c)])));
+        let answers: Vec<_> = q.iter().map(|s| AsScheme(s.reify()).to_string()).collect();
+        assert_eq!(answers, ["(2)"]);
+        assert_eq!(q.stats().unwrap().index_hits, 1);
+        assert_eq!(run_all(colour).len(), 3);
+
+        // Not every clause starts with a ground term for `x`
+        let (x, y) = (Var(0), Var(1));
+        assert!(matches!(cond([[eq(x, 1)], [eq(x, y)]]), Goal::Either(_, _)));
+        assert!(matches!(any([eq(x, 1), eq(x, 1)]), Goal::Either(_, _)));
+    }
+
+    #[test]
+    fn no_index() {
+        let x = Var(0);
+        let clauses = vec![Rc::new(eq(x, 1)), Rc::new(eq(x, 1))];
+        assert!(index(clauses).is_err());
+        let clauses = vec![Rc::new(eq(x, 1)), Rc::new(eq(x, 2))];
+        assert!(matches!(index(clauses), Ok(Goal::Index(_))));
+    }
+}
@@ -0,0 +1,283 @@
+// Relational interpreter for a small Lisp
+//
+// The language is the one used for quine generation in the miniKanren literature: `quote`,
+// variadic `list`, single argument `lambda` and application, plus `if`, which takes `#f` as false.
+// There are no self-evaluating literals, numbers and booleans are quoted like any other data.
+// Symbols are strings and are looked up in an environment, an association list from the `map`
+// module. Procedures evaluate to `(closure x body env)`, `quote` can't produce closures, so
+// `absento` keeps them out of quoted data. The special forms can be shadowed by variables of the
+// same name.
+//
+// `evalo` runs in any direction: evaluating a program, finding programs with a value, or quines
+// with `evalo(q, q)`. Like the interpreters in the miniKanren literature, the clauses that can't
+// recurse come first and application comes last, so the search reaches quines before it gets lost
+// in nested applications. Values are bound with `eq_occurs`, a program can be its own value and a
+// plain `eq` would make cyclic terms.
+//
+// The search suspends once in every application, `if` and `list`. The elements of a `list` are
+// evaluated without suspending when the expressions or the values are already a proper list, so
+// the recursion is bounded, and suspend one by one otherwise. An application evaluates its
+// argument before its body, so the body of a quine is checked against a known value.
+
+use crate::goal;
+use crate::map::not_keyo;
+use crate::*;
+
+pub const CLOSURE: &str = "closure";
+pub const FALSE: &str = "#f";
+
+/// The atom `atom` doesn't occur anywhere in `term`. Checks the bound parts of `term` right
+/// away and waits for the rest
+pub fn absento(atom: impl Into<Term>, term: impl Into<Term>) -> Goal {
+    let atom = atom.into();
+    let term = term.into();
+    let args = [atom.clone(), term.clone()];
+    project_named("absento", args, [term], move |terms| match &terms[0] {
+        // A symbol can only be the atom itself
+        Term::Var(_, TermType::String) => Some(neq(&terms[0], &atom)),
+        Term::Var(_, _) | Term::Type(_) => None,
+        Term::Cons(head, tail) => Some(all([
+            absento(&atom, head.as_ref()),
+            absento(&atom, tail.as_ref()),
+        ])),
+        Term::Set(elements, rest) => {
+            let terms = elements.iter().chain([rest.as_ref()]);
+            Some(all(terms.map(|e| absento(&atom, e))))
+        }
+        Term::Map(entries) if entries.is_empty() => Some(succeed()),
+        Term::Map(entries) => {
+            let terms = entries.iter().flat_map(|(k, v)| [k, v]);
+            Some(all(terms.map(|t| absento(&atom, t))))
+        }
+        term => Some(if *term == atom { fail() } else { succeed() }),
+    })
+}
+
+// `value` is the value of `expr`
+goal!(
+    pub fn evalo(expr: Var, value: Var) -> Goal {
+        eval_expo(expr, NULL, value)
+    }
+);
+
+// `value` is the value of `expr` in the environment `env`
+goal!(
+    pub fn eval_expo(expr: Var, env: Var, value: Var) -> Goal {
+        cond([
+            vec![fresh(move |v| {
+                all([
+                    eq(expr, list!("quote", v)),
+                    not_in_envo(env, "quote"),
+                    absento(CLOSURE, v),
+                    eq_occurs(value, v),
+                ])
+            })],
+            vec![fresh(move |args| {
+                all([
+                    eq(expr, cons("list", args)),
+                    not_in_envo(env, "list"),
+                    jield(move || eval_listo(args, env, value)),
+                ])
+            })],
+            vec![str(expr), lookupo(env, expr, value)],
+            vec![fresh(move |x, body| {
+                all([
+                    eq(expr, list!("lambda", list!(x), body)),
+                    str(x),
+                    not_in_envo(env, "lambda"),
+                    eq_occurs(value, list!(CLOSURE, x, body, env)),
+                ])
+            })],
+            vec![fresh(move |c, t, e, v| {
+                all([
+                    eq(expr, list!("if", c, t, e)),
+                    not_in_envo(env, "if"),
+                    jield(move || {
+                        cond([
+                            vec![eval_expo(t, env, value), neq(v, FALSE)],
+                            vec![eval_expo(e, env, value), eq(v, FALSE)],
+                        ])
+                    }),
+                    jield(move || eval_expo(c, env, v)),
+                ])
+            })],
+            vec![fresh(move |rator, rand, x, body, env2, arg| {
+                all([
+                    eq(expr, list!(rator, rand)),
+                    jield(move || {
+                        all([
+                            eval_expo(rator, env, list!(CLOSURE, x, body, env2)),
+                            eval_expo(rand, env, arg),
+                            eval_expo(body, cons(cons(x, arg), env2), value),
+                        ])
+                    }),
+                ])
+            })],
+        ])
+    }
+);
+
+// The special form `form` isn't shadowed by a variable in `env`
+fn not_in_envo(env: Var, form: &'static str) -> Goal {
+    project([Term::from(env)], move |terms| {
+        Some(match terms[0].to_vec() {
+            Some(entries) if entries.is_empty() => succeed(),
+            Some(entries) => all(entries.iter().map(|entry| match entry {
+                Term::Cons(x, _) => neq(x.as_ref(), form),
+                _ => fail(),
+            })),
+            None => not_keyo(env, form),
+        })
+    })
+}
+
+// `value` is bound to the symbol `x` in `env`
+goal!(
+    fn lookupo(env: Var, x: Var, value: Var) -> Goal {
+        fresh(move |k, v, rest| {
+            cond([
+                vec![eq(env, cons(cons(x, v), rest)), eq_occurs(value, v)],
+                vec![
+                    eq(env, cons(cons(k, v), rest)),
+                    neq(k, x),
+                    jield(move || lookupo(rest, x, value)),
+                ],
+            ])
+        })
+    }
+);
+
+// `values` are the values of the list of expressions `exprs`
+goal!(
+    fn eval_listo(exprs: Var, env: Var, values: Var) -> Goal {
+        fresh(move |a, d, va, vd| {
+            cond([
+                vec![eq(exprs, NULL), eq(values, NULL)],
+                vec![
+                    eq(exprs, cons(a, d)),
+                    eq_occurs(values, cons(va, vd)),
+                    project([Term::from(exprs), Term::from(values)], move |lists| {
+                        let rest = move || all([eval_expo(a, env, va), eval_listo(d, env, vd)]);
+                        Some(if lists.iter().any(|l| l.to_vec().is_some()) {
+                            rest()
+                        } else {
+                            jield(rest)
+                        })
+                    }),
+                ],
+            ])
+        })
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    #[test]
+    fn evaluate() {
+        let result = run_all(|v| evalo(list!("quote", list!("a", 1)), v));
+        assert_eq!(AsScheme(result).to_string(), "(((a 1)))");
+
+        let identity = list!("lambda", list!("x"), "x");
+        let args = list!("list", list!("quote", 1), list!("quote", "#t"));
+        let result = run_all(|v| evalo(list!(identity.clone(), &args), v));
+        assert_eq!(AsScheme(result).to_string(), "(((1 #t)))");
+
+        let program = list!(
+            "if",
+            list!("quote", FALSE),
+            "x",
+            list!("list", list!("quote", 2))
+        );
+        let result = run_all(|v| evalo(&program, v));
+        assert_eq!(AsScheme(result).to_string(), "(((2)))");
+
+        // No self-evaluating literals
+        assert!(run_all(|v| evalo(1, v)).is_empty());
+
+        // Shadowing `quote`, `(quote (list))` applies the identity to `(list)`
+        let body = list!("quote", list!("list"));
+        let program = list!(list!("lambda", list!("quote"), body), identity);
+        let result = run_all(|v| evalo(&program, v));
+        assert_eq!(AsScheme(result).to_string(), "((()))");
+
+        assert!(run_all(|v| evalo("x", v)).is_empty());
+    }
+
+    #[test]
+    fn absent() {
+        let result = run_all(|x| all([absento("a", list!(1, x)), eq(x, list!("b", "a"))]));
+        assert_eq!(AsScheme(result).to_string(), "()");
+
+        let result = run_all(|x| all([absento("a", list!(1, x)), eq(x, list!("b"))]));
+        assert_eq!(AsScheme(result).to_string(), "(((b)))");
+
+        // Still waiting for `x`, so it stays in the answer
+        let result = run_all(|x| absento("a", list!(1, x)));
+        assert_eq!(AsScheme(result).to_string(), "((_0) : ((absento a _0)))");
+    }
+
+    #[test]
+    fn backwards() {
+        let result = run(3, |q| evalo(q, list!("I", "love", "you")));
+        let result: Vec<_> = result.iter().map(StateN::canonical).collect();
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "(((quote (I love you))) \
+             ((list (quote I) (quote love) (quote you))) \
+             (((lambda (*0) (quote (I love you))) (quote _1))) : \
+             (((_0 . quote)) (absento closure _1)))"
+        );
+    }
+
+    fn quine() -> Term {
+        let half = list!(
+            "lambda",
+            list!("x"),
+            list!("list", "x", list!("list", list!("quote", "quote"), "x"))
+        );
+        list!(half.clone(), list!("quote", half))
+    }
+
+    #[test]
+    fn evaluate_quine() {
+        let result = run_all(|v| evalo(quine(), v));
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].reify()[0], quine());
+    }
+
+    #[test]
+    fn quine_body() {
+        // Synthesize the body of a quine
+        let result = run(1, |x, body| {
+            let half = list!("lambda", list!(x), body);
+            let q = list!(&half, list!("quote", &half));
+            evalo(&q, &q)
+        });
+        let result: Vec<_> = result.iter().map(StateN::canonical).collect();
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "((*0 (list *0 (list (quote quote) *0))) : \
+             (((_0 . closure)) ((_0 . list)) ((_0 . quote))))"
+        );
+    }
+
+    #[test]
+    fn generate_quine() {
+        let result = run(1, |q| evalo(q, q));
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            AsScheme(result[0].canonical()).to_string(),
+            "(((lambda (*0) (list *0 (list (quote quote) *0))) \
+             (quote (lambda (*0) (list *0 (list (quote quote) *0)))))) : \
+             (((_0 . closure)) ((_0 . list)) ((_0 . quote)))"
+        );
+
+        // The quoted data is the procedure itself
+        let [program] = result[0].reify();
+        let [rator, rand] = program.to_vec().unwrap().try_into().unwrap();
+        assert_eq!(rand, list!("quote", rator));
+    }
+}
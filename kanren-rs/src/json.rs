@@ -0,0 +1,294 @@
+// Convert between JSON values and terms, enabled with the `json` feature.
+//
+// Encoding:
+// - objects as a tagged association list `(#obj (key . value) ...)`, sorted by key
+// - arrays as lists
+// - numbers as numbers, only integers that fit in an i32
+// - strings as strings, or `(#str . string)` for those starting with `#`
+// - booleans as `#t` and `#f`, null as `#null`
+//
+// Strings are escaped so they are never one of the atoms `#obj`, `#t`, `#f` and `#null`, and
+// every JSON value has exactly one term. Keys are always plain strings.
+
+use crate::goal;
+use crate::list::contains;
+use crate::*;
+
+pub use crate::json_grammar::{ESCAPED, FALSE, NULL_ATOM, OBJECT, TRUE};
+
+use serde_json::{Map, Number, Value};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonError {
+    /// The number isn't an integer that fits in an i32
+    Number(Number),
+    /// The term is still an unbound variable
+    Unbound(Term),
+    /// The term isn't a valid encoding of a JSON value
+    Invalid(Term),
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::display::AsScheme;
+        match self {
+            Self::Number(n) => write!(f, "number {n} doesn't fit in an i32"),
+            Self::Unbound(term) => write!(f, "unbound term {}", AsScheme(term)),
+            Self::Invalid(term) => write!(f, "{} isn't a JSON value", AsScheme(term)),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+fn string(s: &str) -> Term {
+    if s.starts_with('#') {
+        cons(ESCAPED, s)
+    } else {
+        Term::from(s)
+    }
+}
+
+fn list_term(items: Vec<Term>) -> Term {
+    items
+        .into_iter()
+        .rev()
+        .fold(NULL, |tail, item| cons(item, tail))
+}
+
+/// Convert a JSON value into a term
+pub fn to_term(value: &Value) -> Result<Term, JsonError> {
+    Ok(match value {
+        Value::Null => Term::from(NULL_ATOM),
+        Value::Bool(b) => Term::from(if *b { TRUE } else { FALSE }),
+        Value::Number(n) => n
+            .as_i64()
+            .and_then(|i| i32::try_from(i).ok())
+            .map(Term::Value)
+            .ok_or_else(|| JsonError::Number(n.clone()))?,
+        Value::String(s) => string(s),
+        Value::Array(items) => list_term(items.iter().map(to_term).collect::<Result<_, _>>()?),
+        // Sorted, `Map` is ordered by key
+        Value::Object(map) => {
+            let pairs = map
+                .iter()
+                .map(|(k, v)| Ok(cons(k.as_str(), to_term(v)?)))
+                .collect::<Result<_, JsonError>>()?;
+            cons(OBJECT, list_term(pairs))
+        }
+    })
+}
+
+fn invalid(term: &Term) -> JsonError {
+    match term {
+        Term::Var(_, _) | Term::Type(_) => JsonError::Unbound(term.clone()),
+        _ => JsonError::Invalid(term.clone()),
+    }
+}
+
+/// Elements of a proper list
+fn elements(term: &Term) -> Result<Vec<&Term>, JsonError> {
+    let mut items = Vec::new();
+    let mut rest = term;
+    loop {
+        match rest {
+            Term::Null => return Ok(items),
+            Term::Cons(head, tail) => {
+                items.push(head.as_ref());
+                rest = tail;
+            }
+            _ => return Err(invalid(rest)),
+        }
+    }
+}
+
+/// Convert a reified term back into a JSON value
+pub fn from_term(term: &Term) -> Result<Value, JsonError> {
+    Ok(match term {
+        Term::Value(i) => Value::from(*i),
+        Term::String(s) => match s.as_str() {
+            TRUE => Value::Bool(true),
+            FALSE => Value::Bool(false),
+            NULL_ATOM => Value::Null,
+            _ if s.starts_with('#') => return Err(invalid(term)),
+            _ => Value::String(s.clone()),
+        },
+        Term::Cons(head, tail) if matches!(head.as_ref(), Term::String(s) if s == ESCAPED) => {
+            match tail.as_ref() {
+                Term::String(s) if s.starts_with('#') => Value::String(s.clone()),
+                _ => return Err(invalid(term)),
+            }
+        }
+        Term::Cons(head, tail) if matches!(head.as_ref(), Term::String(s) if s == OBJECT) => {
+            let mut map = Map::new();
+            for pair in elements(tail)? {
+                match pair {
+                    Term::Cons(key, value) => match key.as_ref() {
+                        Term::String(k) => {
+                            map.insert(k.clone(), from_term(value)?);
+                        }
+                        _ => return Err(invalid(key)),
+                    },
+                    _ => return Err(invalid(pair)),
+                }
+            }
+            Value::Object(map)
+        }
+        Term::Map(entries) => {
+            let mut map = Map::new();
+            for (key, value) in entries.iter() {
+                match key {
+                    Term::String(k) => {
+                        map.insert(k.clone(), from_term(value)?);
+                    }
+                    _ => return Err(invalid(key)),
+                }
+            }
+            Value::Object(map)
+        }
+        Term::Null | Term::Cons(_, _) => Value::Array(
+            elements(term)?
+                .into_iter()
+                .map(from_term)
+                .collect::<Result<_, _>>()?,
+        ),
+        Term::Var(_, _) | Term::Type(_) | Term::Set(_, _) => return Err(invalid(term)),
+    })
+}
+
+// `value` is stored under `key` in the JSON object `object`
+goal!(
+    pub fn field(object: Var, key: Var, value: Var) -> Goal {
+        fresh(move |pairs| {
+            all([
+                eq(object, cons(OBJECT, pairs)),
+                contains(pairs, cons(key, value)),
+            ])
+        })
+    }
+);
+
+// `value` is an element of the JSON array `array`
+goal!(
+    pub fn element(array: Var, value: Var) -> Goal {
+        contains(array, value)
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn roundtrip() {
+        let value = json!({
+            "name": "kanren",
+            "version": [0, 1],
+            "stable": false,
+            "license": null,
+            "deps": {},
+        });
+        let term = to_term(&value).unwrap();
+        assert_eq!(
+            term,
+            list!(
+                OBJECT,
+                cons("deps", list!(OBJECT)),
+                cons("license", NULL_ATOM),
+                cons("name", "kanren"),
+                cons("stable", FALSE),
+                cons("version", list!(0, 1)),
+            )
+        );
+        assert_eq!(from_term(&term).unwrap(), value);
+
+        // Records convert to objects as well
+        let record = Term::map([("b", list!(1)), ("a", Term::from(TRUE))]);
+        assert_eq!(from_term(&record).unwrap(), json!({"a": true, "b": [1]}));
+    }
+
+    #[test]
+    fn escaped() {
+        // Strings that look like the atoms are values as well
+        let value = json!(["#t", "#f", "#null", "#obj", {"#t": "#str"}, "", "a#"]);
+        let term = to_term(&value).unwrap();
+        assert_eq!(
+            term,
+            list!(
+                cons(ESCAPED, "#t"),
+                cons(ESCAPED, "#f"),
+                cons(ESCAPED, "#null"),
+                cons(ESCAPED, "#obj"),
+                list!(OBJECT, cons("#t", cons(ESCAPED, "#str"))),
+                "",
+                "a#",
+            )
+        );
+        assert_eq!(from_term(&term).unwrap(), value);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            to_term(&json!(1.5)),
+            Err(JsonError::Number(Number::from_f64(1.5).unwrap()))
+        );
+        assert_eq!(
+            to_term(&json!(1u64 << 40)),
+            Err(JsonError::Number(Number::from(1u64 << 40)))
+        );
+        assert_eq!(
+            from_term(&list!(1, Var(0))),
+            Err(JsonError::Unbound(Term::from(Var(0))))
+        );
+        assert_eq!(
+            from_term(&cons(1, 2)),
+            Err(JsonError::Invalid(Term::from(2)))
+        );
+        assert_eq!(
+            from_term(&Term::from("#x")),
+            Err(JsonError::Invalid(Term::from("#x")))
+        );
+        assert_eq!(
+            from_term(&cons(ESCAPED, "x")),
+            Err(JsonError::Invalid(cons(ESCAPED, "x")))
+        );
+    }
+
+    #[test]
+    fn query() {
+        let config = to_term(&json!({
+            "services": [
+                {"name": "web", "port": 80},
+                {"name": "db", "port": 5432},
+                {"name": "cache"},
+            ]
+        }))
+        .unwrap();
+
+        // Names of the services with a port
+        let result = run_all(|name| {
+            let config = config.clone();
+            fresh(move |services, service, port| {
+                all([
+                    field(&config, "services", services),
+                    element(services, service),
+                    field(service, "name", name),
+                    field(service, "port", port),
+                ])
+            })
+        });
+        let names: Vec<Value> = result
+            .iter()
+            .map(|s| from_term(&s.reify()[0]).unwrap())
+            .collect();
+        assert_eq!(names, vec![json!("web"), json!("db")]);
+
+        // Build a JSON value relationally
+        let result = run(1, |doc| field(doc, "ok", TRUE));
+        let doc = result[0].reify()[0].clone();
+        assert!(matches!(from_term(&doc), Err(JsonError::Unbound(_))));
+    }
+}
@@ -0,0 +1,521 @@
+// Relational JSON grammar, runnable in both directions.
+//
+// Text is a list of single character strings, see `chars`, or a string atom with `json_text`.
+// The tokenizer relates text to a list of tokens:
+// - punctuation `{ } [ ] : ,` as the same strings
+// - `true`, `false` and `null` as the strings "true", "false" and "null"
+// - strings as `(str c ...)` with the characters between the quotes, escapes aren't supported
+// - integers as `(num c ...)` with the characters of the number, e.g. `(num - 4 2)`
+//
+// The grammar relates tokens, or text directly, to values in the encoding of the `json` module, so
+// `json::from_term` converts them. Strings and numbers are converted from and to the characters of
+// their tokens once those are bound, see `string_charso`. Object members are sorted by key like
+// `json::to_term` sorts them, text generated from an object lists them in every order, sorted
+// first. Of duplicate keys the last is kept, like `serde_json` does.
+
+use crate::goal;
+use crate::*;
+
+pub use crate::strings::{chars, from_chars};
+use crate::strings::string_charso;
+
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+const WHITESPACE: [&str; 4] = [" ", "\n", "\t", "\r"];
+const PUNCTUATION: [&str; 6] = ["{", "}", "[", "]", ":", ","];
+const KEYWORDS: [&str; 3] = ["true", "false", "null"];
+
+/// Tag of objects, `(#obj (key . value) ...)`
+pub const OBJECT: &str = "#obj";
+pub const TRUE: &str = "#t";
+pub const FALSE: &str = "#f";
+pub const NULL_ATOM: &str = "#null";
+/// Tag of strings starting with `#`, `(#str . string)`, so they are never one of the atoms
+pub const ESCAPED: &str = "#str";
+
+goal!(
+    pub fn digit(c: Var) -> Goal {
+        any(DIGITS.map(|d| eq(c, d)))
+    }
+);
+
+goal!(
+    pub fn whitespace(c: Var) -> Goal {
+        any(WHITESPACE.map(|w| eq(c, w)))
+    }
+);
+
+// `chars` doesn't continue with a digit
+goal!(
+    fn boundary(chars: Var) -> Goal {
+        cond([
+            vec![eq(chars, NULL)],
+            vec![fresh(move |c, tail| {
+                all([eq(chars, cons(c, tail)), all(DIGITS.map(|d| neq(c, d)))])
+            })],
+        ])
+    }
+);
+
+// The longest run of digits `ds` at the start of `chars`, followed by `rest`
+goal!(
+    fn digits(chars: Var, rest: Var, ds: Var) -> Goal {
+        fresh(move |d, tail, ds_tail| {
+            all([
+                eq(chars, cons(d, tail)),
+                eq(ds, cons(d, ds_tail)),
+                digit(d),
+                cond([
+                    vec![eq(ds_tail, NULL), eq(tail, rest), boundary(rest)],
+                    vec![jield(move || digits(tail, rest, ds_tail))],
+                ]),
+            ])
+        })
+    }
+);
+
+// Digits without leading zeros
+goal!(
+    fn unsigned(chars: Var, rest: Var, ds: Var) -> Goal {
+        all([
+            digits(chars, rest, ds),
+            cond([
+                vec![eq(ds, list!("0"))],
+                vec![fresh(move |d, tail| {
+                    all([eq(ds, cons(d, tail)), neq(d, "0")])
+                })],
+            ]),
+        ])
+    }
+);
+
+goal!(
+    fn number(chars: Var, rest: Var, cs: Var) -> Goal {
+        cond([
+            vec![fresh(move |tail, ds| {
+                all([
+                    eq(chars, cons("-", tail)),
+                    eq(cs, cons("-", ds)),
+                    unsigned(tail, rest, ds),
+                ])
+            })],
+            vec![unsigned(chars, rest, cs)],
+        ])
+    }
+);
+
+// Characters `cs` of a string up to the closing quote, followed by `rest`
+goal!(
+    fn string_body(chars: Var, rest: Var, cs: Var) -> Goal {
+        cond([
+            vec![eq(chars, cons("\"", rest)), eq(cs, NULL)],
+            vec![fresh(move |c, tail, cs_tail| {
+                all([
+                    eq(chars, cons(c, tail)),
+                    eq(cs, cons(c, cs_tail)),
+                    neq(c, "\""),
+                    neq(c, "\\"),
+                    jield(move || string_body(tail, rest, cs_tail)),
+                ])
+            })],
+        ])
+    }
+);
+
+goal!(
+    fn keyword(chars: Var, rest: Var, token: Var) -> Goal {
+        any(KEYWORDS.map(|k| {
+            let text = k
+                .chars()
+                .rev()
+                .fold(Term::from(rest), |tail, c| cons(c.to_string(), tail));
+            all([eq(token, k), eq(chars, text)])
+        }))
+    }
+);
+
+// A single token at the start of `chars`, followed by `rest`
+goal!(
+    pub fn token(chars: Var, rest: Var, token: Var) -> Goal {
+        cond([
+            vec![any(
+                PUNCTUATION.map(|p| all([eq(token, p), eq(chars, cons(p, rest))]))
+            )],
+            vec![keyword(chars, rest, token)],
+            vec![fresh(move |tail, cs| {
+                all([
+                    eq(chars, cons("\"", tail)),
+                    eq(token, cons("str", cs)),
+                    string_body(tail, rest, cs),
+                ])
+            })],
+            vec![fresh(move |cs| {
+                all([eq(token, cons("num", cs)), number(chars, rest, cs)])
+            })],
+        ])
+    }
+);
+
+// Whitespace at the start of `chars`, followed by `rest`
+goal!(
+    fn skip_whitespace(chars: Var, rest: Var) -> Goal {
+        cond([
+            vec![eq(chars, rest)],
+            vec![fresh(move |c, tail| {
+                all([
+                    eq(chars, cons(c, tail)),
+                    whitespace(c),
+                    jield(move || skip_whitespace(tail, rest)),
+                ])
+            })],
+        ])
+    }
+);
+
+// Tokenizer, relates text to a list of tokens, skipping whitespace
+goal!(
+    pub fn tokens(chars: Var, toks: Var) -> Goal {
+        fresh(move |start| {
+            all([
+                skip_whitespace(chars, start),
+                cond([
+                    vec![eq(start, NULL), eq(toks, NULL)],
+                    vec![fresh(move |t, rest, ts| {
+                        all([
+                            eq(toks, cons(t, ts)),
+                            token(start, rest, t),
+                            jield(move || tokens(rest, ts)),
+                        ])
+                    })],
+                ]),
+            ])
+        })
+    }
+);
+
+/// Relates the start of the input to a token, followed by `rest`.
+/// The grammar is generic over the input, a list of tokens or text.
+type Lexer = fn(Var, Var, Term) -> Goal;
+
+fn next_token(toks: Var, rest: Var, token: Term) -> Goal {
+    eq(toks, cons(token, rest))
+}
+
+fn next_char_token(chars: Var, rest: Var, t: Term) -> Goal {
+    fresh(move |start| all([skip_whitespace(chars, start), token(start, rest, &t)]))
+}
+
+// `expr` is the string value with the characters `cs`, escaped when it starts with `#`
+fn string(cs: Var, expr: Var) -> Goal {
+    fresh(move |s, c, rest| {
+        cond([
+            vec![
+                eq(cs, cons("#", rest)),
+                eq(expr, cons(ESCAPED, s)),
+                string_charso(s, cs),
+            ],
+            vec![eq(cs, NULL), eq(expr, "")],
+            vec![
+                eq(cs, cons(c, rest)),
+                neq(c, "#"),
+                string_charso(expr, cs),
+            ],
+        ])
+    })
+}
+
+// `sorted` are the object members `ms` sorted by key, the last of duplicate keys kept
+fn sorted_members(ms: Var, sorted: Var) -> Goal {
+    let terms = [Term::from(ms), Term::from(sorted)];
+    project_named("sorted_members", terms.clone(), terms, |terms| {
+        let [ms, sorted] = terms else { unreachable!() };
+        if ms.is_ground() {
+            let ms = ms.to_vec()?;
+            let mut members: Vec<(&Term, &Term)> = Vec::new();
+            for m in &ms {
+                match m {
+                    Term::Cons(key, value) => members.push((key, value)),
+                    _ => return Some(fail()),
+                }
+            }
+            members.sort_by_key(|(key, _)| *key);
+            let mut unique: Vec<(&Term, &Term)> = Vec::new();
+            for (key, value) in members {
+                match unique.last_mut() {
+                    Some((last, v)) if *last == key => *v = value,
+                    _ => unique.push((key, value)),
+                }
+            }
+            let unique = unique.into_iter().rev();
+            Some(eq(sorted, unique.fold(NULL, |tail, (k, v)| cons(cons(k, v), tail))))
+        } else if sorted.is_ground() {
+            Some(list::permuteo(sorted, ms))
+        } else {
+            None
+        }
+    })
+}
+
+// `n` is the number with the characters `cs`
+fn number_chars(cs: Var, n: Var) -> Goal {
+    project([cs.into(), n.into()], |terms| {
+        let [cs, n] = terms else { unreachable!() };
+        match n {
+            Term::Value(i) => return Some(eq(cs, chars(&i.to_string()))),
+            Term::Var(_, _) => {}
+            _ => return Some(fail()),
+        }
+        let text = from_chars(cs)?;
+        Some(match text.parse::<i32>() {
+            Ok(i) => eq(n, i),
+            Err(_) => fail(),
+        })
+    })
+}
+
+fn member(lex: Lexer, input: Var, rem: Var, expr: Var) -> Goal {
+    fresh(move |cs, k, colon, e, v| {
+        all([
+            eq(expr, cons(k, v)),
+            string_charso(k, cs),
+            lex(input, colon, cons("str", cs)),
+            lex(colon, e, Term::from(":")),
+            value(lex, e, rem, v),
+        ])
+    })
+}
+
+fn members(lex: Lexer, input: Var, rem: Var, expr: Var) -> Goal {
+    cond([
+        vec![fresh(move |e| {
+            all([eq(expr, list!(e)), member(lex, input, rem, e)])
+        })],
+        vec![fresh(move |e, es, comma, ms| {
+            all([
+                eq(expr, cons(e, es)),
+                member(lex, input, comma, e),
+                lex(comma, ms, Term::from(",")),
+                jield(move || members(lex, ms, rem, es)),
+            ])
+        })],
+    ])
+}
+
+fn object(lex: Lexer, input: Var, rem: Var, expr: Var) -> Goal {
+    fresh(move |tail| {
+        all([
+            lex(input, tail, Term::from("{")),
+            cond([
+                vec![eq(expr, list!(OBJECT)), lex(tail, rem, Term::from("}"))],
+                vec![fresh(move |close, ms, sorted| {
+                    all([
+                        eq(expr, cons(OBJECT, sorted)),
+                        sorted_members(ms, sorted),
+                        members(lex, tail, close, ms),
+                        lex(close, rem, Term::from("}")),
+                    ])
+                })],
+            ]),
+        ])
+    })
+}
+
+fn elements(lex: Lexer, input: Var, rem: Var, expr: Var) -> Goal {
+    cond([
+        vec![fresh(move |e| {
+            all([eq(expr, list!(e)), value(lex, input, rem, e)])
+        })],
+        vec![fresh(move |e, es, comma, ms| {
+            all([
+                eq(expr, cons(e, es)),
+                value(lex, input, comma, e),
+                lex(comma, ms, Term::from(",")),
+                jield(move || elements(lex, ms, rem, es)),
+            ])
+        })],
+    ])
+}
+
+fn array(lex: Lexer, input: Var, rem: Var, expr: Var) -> Goal {
+    fresh(move |tail| {
+        all([
+            lex(input, tail, Term::from("[")),
+            cond([
+                vec![eq(expr, NULL), lex(tail, rem, Term::from("]"))],
+                vec![fresh(move |close| {
+                    all([
+                        elements(lex, tail, close, expr),
+                        lex(close, rem, Term::from("]")),
+                    ])
+                })],
+            ]),
+        ])
+    })
+}
+
+// A value at the start of `input`, followed by `rem`
+fn value(lex: Lexer, input: Var, rem: Var, expr: Var) -> Goal {
+    cond([
+        vec![eq(expr, TRUE), lex(input, rem, Term::from("true"))],
+        vec![eq(expr, FALSE), lex(input, rem, Term::from("false"))],
+        vec![eq(expr, NULL_ATOM), lex(input, rem, Term::from("null"))],
+        vec![fresh(move |cs| {
+            all([string(cs, expr), lex(input, rem, cons("str", cs))])
+        })],
+        vec![fresh(move |cs| {
+            all([number_chars(cs, expr), lex(input, rem, cons("num", cs))])
+        })],
+        vec![jield(move || object(lex, input, rem, expr))],
+        vec![jield(move || array(lex, input, rem, expr))],
+    ])
+}
+
+// Grammar, relates a list of tokens to a value
+goal!(
+    pub fn json_tokens(toks: Var, expr: Var) -> Goal {
+        fresh(move |rem| all([eq(rem, NULL), value(next_token, toks, rem, expr)]))
+    }
+);
+
+// Relates text to a value, without a separate tokenizer pass so it runs in both directions
+goal!(
+    pub fn json(chars: Var, expr: Var) -> Goal {
+        fresh(move |rem| {
+            all([
+                value(next_char_token, chars, rem, expr),
+                skip_whitespace(rem, NULL),
+            ])
+        })
+    }
+);
+
+// Relates a string to a value
+goal!(
+    pub fn json_text(text: Var, expr: Var) -> Goal {
+        fresh(move |chars| all([string_charso(text, chars), json(chars, expr)]))
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    #[test]
+    fn tokenize() {
+        let result = run_all(|toks| tokens(chars("{\"a\": [1, -20, 0],\n\"b\":true}"), toks));
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "((({ (str a) : [ (num 1) , (num - 2 0) , (num 0) ] , (str b) : true })))"
+        );
+
+        // Leading zeros and unterminated strings aren't tokens
+        assert!(run_all(|toks| tokens(chars("01"), toks)).is_empty());
+        assert!(run_all(|toks| tokens(chars("\"a"), toks)).is_empty());
+
+        // Numbers are split by whitespace
+        let result = run_all(|toks| tokens(chars("1 2"), toks));
+        assert_eq!(AsScheme(result).to_string(), "((((num 1) (num 2))))");
+    }
+
+    #[test]
+    fn untokenize() {
+        let result = run(1, |text| {
+            tokens(
+                text,
+                list!(cons("num", list!("1")), cons("num", list!("2"))),
+            )
+        });
+        assert_eq!(from_chars(&result[0].reify()[0]).as_deref(), Some("1 2"));
+
+        let result = run(1, |text| {
+            tokens(text, list!("[", "true", ",", cons("str", list!("x")), "]"))
+        });
+        assert_eq!(
+            from_chars(&result[0].reify()[0]).as_deref(),
+            Some("[true,\"x\"]")
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let result = run_all(|expr| json(chars("{}"), expr));
+        assert_eq!(AsScheme(result).to_string(), "(((#obj)))");
+
+        let result = run_all(|expr| {
+            json(
+                chars("{\"hello\": \"world\", \"good\": [null, false]}"),
+                expr,
+            )
+        });
+        #[cfg(feature = "json")]
+        {
+            let value = crate::json::from_term(&result[0].reify()[0]).unwrap();
+            assert_eq!(value.to_string(), r#"{"good":[null,false],"hello":"world"}"#);
+        }
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "(((#obj (good #null #f) (hello . world))))"
+        );
+
+        // Strings starting with `#` are escaped, the last of duplicate keys is kept
+        let text = r##"{"#t": ["#t", "x", "#"], "b": 1, "a": 2, "b": 3}"##;
+        let result = run_all(|expr| json(chars(text), expr));
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "(((#obj (#t (#str . #t) x (#str . #)) (a . 2) (b . 3))))"
+        );
+        #[cfg(feature = "json")]
+        {
+            let value: serde_json::Value = serde_json::from_str(text).unwrap();
+            let result = run_all(|expr| json(chars(text), expr));
+            assert_eq!(result[0].reify()[0], crate::json::to_term(&value).unwrap());
+        }
+
+        // Numbers that don't fit in an i32 aren't values
+        assert!(run_all(|expr| json(chars("4294967296"), expr)).is_empty());
+
+        assert!(run_all(|expr| json(chars("[1,]"), expr)).is_empty());
+    }
+
+    #[test]
+    fn generate() {
+        let value = list!("#obj", cons("k", list!(-7, "#t")));
+        let result = run(1, |text| json(text, &value));
+        let text = from_chars(&result[0].reify()[0]).unwrap();
+        assert_eq!(text, "{\"k\":[-7,true]}");
+
+        let result = run_all(|expr| json(chars(&text), expr));
+        assert_eq!(result[0].reify()[0], value);
+
+        let result = run(1, |toks| json_tokens(toks, &value));
+        assert_eq!(
+            AsScheme(&result[0].reify()[0]).to_string(),
+            "({ (str k) : [ (num - 7) , true ] })"
+        );
+
+        let result = run_all(|expr| json_tokens(&result[0].reify()[0], expr));
+        assert_eq!(result[0].reify()[0], value);
+
+        let result = run(1, |text| json_text(text, &value));
+        assert_eq!(result[0].reify()[0], Term::from("{\"k\":[-7,true]}"));
+
+        let result = run_all(|expr| json_text("[ -7 ]", expr));
+        assert_eq!(AsScheme(result).to_string(), "(((-7)))");
+
+        // Members in every order, sorted first
+        let value = list!(OBJECT, cons("a", 1), cons("b", cons(ESCAPED, "#")));
+        let result = run_all(|text| json_tokens(text, &value));
+        let texts: Vec<_> = result.iter().map(|s| AsScheme(&s.reify()[0]).to_string()).collect();
+        assert_eq!(
+            texts,
+            [
+                "({ (str a) : (num 1) , (str b) : (str #) })",
+                "({ (str b) : (str #) , (str a) : (num 1) })",
+            ]
+        );
+
+        // Enumerate token streams with their values
+        let result = run(10, json_tokens);
+        assert_eq!(result.len(), 10);
+    }
+}
@@ -1,9 +1,31 @@
+extern crate self as kanren_rs;
+
+pub mod clpset;
+pub mod convert;
 pub mod display;
+pub mod events;
+pub mod facts;
+pub mod index;
+pub mod interp;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod json_grammar;
 pub mod list;
+pub mod map;
+pub mod optimize;
+pub mod pretty;
+pub mod relset;
+#[cfg(feature = "serde")]
+mod serialize;
 pub mod set;
+pub mod ski;
+pub mod stats;
+pub mod strings;
 mod test;
+pub mod trace;
 
 use std::{
+    borrow::Cow,
     cell::RefCell,
     cmp::{max, min},
     collections::{HashMap, HashSet},
@@ -11,8 +33,14 @@ use std::{
     ops::Deref,
     rc::Rc,
     sync::atomic::{AtomicU32, Ordering},
+    time::Instant,
 };
 
+pub use convert::{FromTerm, FromTermError, IntoTerm, IntoTermError, TryIntoTerm};
+pub use stats::Stats;
+pub use trace::Tracer;
+pub use kanren_derive::{FromTerm, IntoTerm, Term};
+
 // TODO:
 // - impl Goal + 'recursive' types
 // - Prefer non-yield goals in eval of Both
@@ -45,9 +73,22 @@ pub enum Term {
     Var(Var, TermType),
     Cons(Rc<Term>, Rc<Term>),
     Null,
+    Set(Rc<Vec<Term>>, Rc<Term>),
+    Map(Rc<Vec<(Term, Term)>>),
 }
 
 impl Term {
+    /// Term without variables or type placeholders
+    pub fn is_ground(&self) -> bool {
+        match self {
+            Term::Var(_, _) | Term::Type(_) => false,
+            Term::Cons(a, b) => a.is_ground() && b.is_ground(),
+            Term::Set(elements, rest) => elements.iter().all(Term::is_ground) && rest.is_ground(),
+            Term::Map(entries) => entries.iter().all(|(k, v)| k.is_ground() && v.is_ground()),
+            _ => true,
+        }
+    }
+
     pub fn to_vec(&self) -> Option<Vec<Term>> {
         fn inner(term: &Term, mut list: Vec<Term>) -> Option<Vec<Term>> {
             match term {
@@ -122,14 +163,25 @@ macro_rules! list {
 macro_rules! goal {
     ( $pub:vis fn $name:ident ($($terms:ident : Var ),+ ) -> Goal $goal:block)  => (
         paste::paste!{
+            #[allow(clippy::too_many_arguments)]
             $pub fn $name ( $($terms : impl Into<Term>),+ ) -> Goal {
-                $(let [<term_ $terms>]: Term = $terms.into();)+
-                fresh(move | $( [<var_ $terms>] ),+ | all([
-                    $(eq(&[<term_ $terms>], [<var_ $terms>]),)+
-                    (| $($terms),+ | $goal)(
-                        $([<var_ $terms>]),+
-                    )
-                ]))
+                // The body and the `Relation` goal share the arguments, recording the call for
+                // tracers and statistics copies nothing
+                let args = std::rc::Rc::new([$(Into::<Term>::into($terms)),+]);
+                let id = $crate::RelationId {
+                    module: module_path!(),
+                    name: stringify!($name),
+                };
+                let shared = args.clone() as std::rc::Rc<[Term]>;
+                $crate::relation(id, shared, fresh(move | $( [<var_ $terms>] ),+ | {
+                    let [$([<term_ $terms>]),+] = &*args;
+                    all([
+                        $(eq([<term_ $terms>], [<var_ $terms>]),)+
+                        (| $($terms),+ | $goal)(
+                            $([<var_ $terms>]),+
+                        )
+                    ])
+                }))
             }
         }
     )
@@ -154,11 +206,31 @@ fn resolve<'a>(term: &'a Term, map: &'a Mapping) -> &'a Term {
     }
 }
 
+// `var` occurs in `term`, binding it would make a cyclic term
+fn occurs(var: Var, term: &Term, map: &Mapping) -> bool {
+    match resolve(term, map) {
+        Term::Var(v, _) => *v == var,
+        Term::Cons(a, b) => occurs(var, a, map) || occurs(var, b, map),
+        Term::Set(elements, rest) => {
+            elements.iter().any(|e| occurs(var, e, map)) || occurs(var, rest, map)
+        }
+        Term::Map(entries) => entries.iter().any(|(_, v)| occurs(var, v, map)),
+        _ => false,
+    }
+}
+
 fn deep_resolve(term: &Term, map: &Mapping) -> Term {
     let term = resolve(term, map);
 
     match term {
         Term::Cons(a, b) => cons(deep_resolve(a, map), deep_resolve(b, map)),
+        Term::Set(elements, rest) => clpset::normalize(
+            elements.iter().map(|e| deep_resolve(e, map)).collect(),
+            deep_resolve(rest, map),
+        ),
+        Term::Map(entries) => Term::Map(Rc::new(
+            entries.iter().map(|(k, v)| (k.clone(), deep_resolve(v, map))).collect(),
+        )),
         _ => term.clone(),
     }
 }
@@ -175,6 +247,10 @@ fn unify_type(a: TermType, b: TermType) -> Option<TermType> {
 struct Unify {
     map: Mapping,
     new: Vec<(Var, Term)>,
+    // Equations between sets that aren't both ground, solved by `clpset::unify`
+    sets: Vec<(Term, Term)>,
+    // Fail instead of binding a variable to a term containing it, for `eq_occurs`
+    occurs_check: bool,
 }
 
 impl Unify {
@@ -182,6 +258,8 @@ impl Unify {
         Self {
             map,
             new: Vec::new(),
+            sets: Vec::new(),
+            occurs_check: false,
         }
     }
 
@@ -244,6 +322,9 @@ impl Unify {
                 | (T::Var(var, TermType::String), term @ Term::String(_))
                 | (term @ Term::String(_), T::Var(var, TermType::String))
             => {
+                if self.occurs_check && occurs(var, &term, &self.map) {
+                    return None;
+                }
                 self.extend(var, term);
                 Some(())
             }
@@ -251,18 +332,77 @@ impl Unify {
                 self.unify(&a_head, &b_head)?;
                 self.unify(&a_tail, &b_tail)
             }
+            (T::Map(a), T::Map(b)) => {
+                // Closed records, the keys have to match exactly
+                if a.len() != b.len() || a.iter().zip(b.iter()).any(|((a, _), (b, _))| a != b) {
+                    return None;
+                }
+                for ((_, a), (_, b)) in a.iter().zip(b.iter()) {
+                    self.unify(a, b)?;
+                }
+                Some(())
+            }
+            (a @ T::Set(_, _), b @ T::Set(_, _)) => {
+                let a = deep_resolve(&a, &self.map);
+                let b = deep_resolve(&b, &self.map);
+                if a.is_ground() && b.is_ground() {
+                    (a == b).then_some(())
+                } else {
+                    self.sets.push((a, b));
+                    Some(())
+                }
+            }
             _ => None,
         }
     }
 }
 
-type Constraint = Vec<(Var, Term)>;
-type Constraints = Vec<Constraint>;
+pub type Constraint = Vec<(Var, Term)>;
+pub type Constraints = Vec<Constraint>;
+
+pub type ProjectFn = Rc<dyn Fn(&[Term]) -> Option<Goal>>;
+
+/// Constraint a delayed `project` stands for in answers, like `(absento closure _.0)`. A
+/// `project` without a name stands for `(project terms...)`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct DelayedForm {
+    pub name: Cow<'static, str>,
+    pub args: Vec<Term>,
+}
+
+/// Project goal waiting for its terms to be bound further
+#[derive(Clone)]
+struct Delayed {
+    terms: Vec<Term>,
+    f: ProjectFn,
+    form: Option<Rc<DelayedForm>>,
+}
+
+impl Debug for Delayed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Delayed").field(&self.terms).finish()
+    }
+}
+
+/// Tracer of a query, shared by all its states
+#[derive(Clone)]
+struct Tracing(Rc<dyn Tracer>);
+
+impl Debug for Tracing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Tracing")
+    }
+}
 
 #[derive(Default, Debug, Clone)]
 pub struct State {
     map: Mapping,
     constraints: Constraints,
+    delayed: Vec<Delayed>,
+    tracer: Option<Tracing>,
+    stats: Option<Rc<RefCell<Stats>>>,
     pub depth: u32,
     pub id: Rc<AtomicU32>,
 }
@@ -273,6 +413,53 @@ impl State {
         deep_resolve(&term, &self.map)
     }
 
+    fn project(&self, terms: &[Term]) -> Vec<Term> {
+        terms.iter().map(|t| deep_resolve(t, &self.map)).collect()
+    }
+
+    /// Run the delayed goals that are ready after the substitution was extended
+    fn wake(mut self) -> Stream {
+        let mut ready = Vec::new();
+        for delayed in std::mem::take(&mut self.delayed) {
+            match (delayed.f)(&self.project(&delayed.terms)) {
+                Some(goal) => ready.push(goal),
+                None => self.delayed.push(delayed),
+            }
+        }
+
+        if ready.is_empty() {
+            Stream::new(self)
+        } else {
+            self.call_made(all(ready))
+        }
+    }
+
+    /// Call `goal` made during the search rather than taken from the goal tree. A tracer gets
+    /// it first, so it can keep the goal alive and apart from goals made later.
+    fn call_made(&self, goal: Goal) -> Stream {
+        match &self.tracer {
+            Some(Tracing(tracer)) => {
+                let goal = Rc::new(goal);
+                tracer.made(&goal);
+                goal.call(self)
+            }
+            None => goal.call(self),
+        }
+    }
+
+    fn trace(&self, f: impl FnOnce(&dyn Tracer)) {
+        if let Some(Tracing(tracer)) = &self.tracer {
+            f(tracer.as_ref())
+        }
+    }
+
+    /// Update the statistics, if the query keeps them
+    fn record(&self, f: impl FnOnce(&mut Stats)) {
+        if let Some(stats) = &self.stats {
+            f(&mut stats.borrow_mut())
+        }
+    }
+
     fn var(&mut self) -> Var {
         let id = self
             .id
@@ -289,6 +476,9 @@ pub enum Goal {
     Either(Rc<Goal>, Rc<Goal>),
     Fresh(RefCell<FreshInner>),
     Yield(RefCell<YieldInner>),
+    Project(Vec<Term>, ProjectFn, Option<Rc<DelayedForm>>),
+    Relation(RelationId, Rc<[Term]>, Rc<Goal>),
+    Index(Rc<index::ClauseIndex>),
 }
 
 pub enum FreshInner {
@@ -305,6 +495,21 @@ pub fn eq(a: impl Into<Term>, b: impl Into<Term>) -> Goal {
     Goal::Eq(a.into(), b.into())
 }
 
+/// `eq` with an occurs check, failing where `eq` would bind a variable to a term containing it
+/// and so make a cyclic term. The check walks both terms, so it's only used by relations that
+/// can unify a term with a part of itself, like `interp::evalo`.
+pub fn eq_occurs(a: impl Into<Term>, b: impl Into<Term>) -> Goal {
+    let (a, b) = (a.into(), b.into());
+    project([a.clone(), b.clone()], move |terms| {
+        let mut u = Unify::new(Mapping::default());
+        u.occurs_check = true;
+        Some(match u.unify(&terms[0], &terms[1]) {
+            Some(_) => eq(&a, &b),
+            None => fail(),
+        })
+    })
+}
+
 pub fn neq(a: impl Into<Term>, b: impl Into<Term>) -> Goal {
     Goal::Neq(a.into(), b.into())
 }
@@ -335,6 +540,53 @@ pub fn jield(f: impl Fn() -> Goal + 'static) -> Goal {
     Goal::Yield(RefCell::new(YieldInner::Pending(Rc::new(f))))
 }
 
+/// Call `f` with the resolved `terms`. When `f` returns `None` the terms aren't bound enough
+/// yet, and `f` is called again each time the substitution is extended until it returns a goal.
+/// Goals still delayed at the end of a query stay in the answer as residual goals, see
+/// `project_named` to show them under a name.
+pub fn project(
+    terms: impl IntoIterator<Item = Term>,
+    f: impl Fn(&[Term]) -> Option<Goal> + 'static,
+) -> Goal {
+    Goal::Project(terms.into_iter().collect(), Rc::new(f), None)
+}
+
+/// `project` shown as the constraint `name` on `args` in answers while it is delayed, see
+/// `display::AsMiniKanren`
+pub fn project_named(
+    name: &'static str,
+    args: impl IntoIterator<Item = Term>,
+    terms: impl IntoIterator<Item = Term>,
+    f: impl Fn(&[Term]) -> Option<Goal> + 'static,
+) -> Goal {
+    let form = DelayedForm {
+        name: Cow::Borrowed(name),
+        args: args.into_iter().collect(),
+    };
+    Goal::Project(terms.into_iter().collect(), Rc::new(f), Some(Rc::new(form)))
+}
+
+/// Relation `name` defined in the module `module`, like `list::append`. Relations of different
+/// modules can have the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RelationId {
+    pub module: &'static str,
+    pub name: &'static str,
+}
+
+/// `goal` as the relation `id` called with `args`, for statistics and debugging output
+pub fn relation(id: RelationId, args: impl Into<Rc<[Term]>>, goal: Goal) -> Goal {
+    Goal::Relation(id, args.into(), Rc::new(goal))
+}
+
+pub fn succeed() -> Goal {
+    Goal::Eq(NULL, NULL)
+}
+
+pub fn fail() -> Goal {
+    Goal::Eq(Term::Value(0), Term::Value(1))
+}
+
 pub fn all(v: impl IntoIterator<Item = Goal>) -> Goal {
     fn inner(mut iter: impl Iterator<Item = Goal>) -> Option<Goal> {
         let a = iter.next()?;
@@ -347,6 +599,8 @@ pub fn all(v: impl IntoIterator<Item = Goal>) -> Goal {
     inner(v.into_iter()).unwrap()
 }
 
+/// Disjunction of `v`, indexed when every goal starts by unifying the same variable with a
+/// different ground term, see `index`
 pub fn any(v: impl IntoIterator<Item = Goal>) -> Goal {
     fn inner(mut iter: impl Iterator<Item = Goal>) -> Option<Goal> {
         let a = iter.next()?;
@@ -356,7 +610,10 @@ pub fn any(v: impl IntoIterator<Item = Goal>) -> Goal {
         }
     }
 
-    inner(v.into_iter()).unwrap()
+    match index::index_heads(v.into_iter().collect()) {
+        Ok(index) => index,
+        Err(goals) => inner(goals.into_iter()).unwrap(),
+    }
 }
 
 pub fn cond<T, R>(table: T) -> Goal
@@ -421,12 +678,15 @@ fn mappend(goal: &Rc<Goal>, stream: Stream) -> Stream {
 fn verify(map: &Mapping, constraints: &Constraints, new: &mut Constraints) -> bool {
     for elements in constraints {
         let mut u = Unify::new(map.clone());
-        let x = elements.iter().fold(Some(()), |r, element| {
-            r.and_then(|_| u.unify(&Term::Var(element.0, TermType::Any), &element.1))
+        let x = elements.iter().try_fold((), |_, element| {
+            u.unify(&Term::Var(element.0, TermType::Any), &element.1)
         });
 
         if x.is_some() {
-            if u.new.is_empty() {
+            if !u.sets.is_empty() {
+                // Depends on how the sets unify, keep the constraint as is
+                new.push(elements.clone());
+            } else if u.new.is_empty() {
                 // Unification without addition, so constraint is violated
                 return false;
             } else {
@@ -443,39 +703,96 @@ fn verify(map: &Mapping, constraints: &Constraints, new: &mut Constraints) -> bo
 
 impl Goal {
     fn call(&self, state: &State) -> Stream {
+        state.record(|s| s.depth(state.depth).goals += 1);
+        match &state.tracer {
+            None => self.step(state),
+            Some(Tracing(tracer)) => {
+                tracer.enter(self, state);
+                let stream = self.step(state);
+                tracer.exit(self, state, &stream);
+                stream
+            }
+        }
+    }
+
+    fn step(&self, state: &State) -> Stream {
         use Goal::*;
 
         match self {
             Eq(a, b) => {
+                state.record(|s| s.unifications += 1);
                 let mut u = Unify::new(state.map.clone());
-                match u.unify(a, b) {
-                    Some(_) if u.new.is_empty() => Stream::new(state.clone()),
+                let stream = match u.unify(a, b) {
+                    Some(_) if u.new.is_empty() => {
+                        state.trace(|t| t.unify(a, b, state, true));
+                        Stream::new(state.clone())
+                    }
                     Some(_) => {
                         let mut constraints = Vec::new();
-                        if verify(&u.map, &state.constraints, &mut constraints) {
-                            Stream::new(State {
+                        let success = verify(&u.map, &state.constraints, &mut constraints);
+                        state.trace(|t| t.unify(a, b, state, success));
+
+                        state.record(|s| {
+                            s.constraints_checked += state.constraints.len() as u64;
+                            s.failures += u64::from(!success);
+                        });
+                        if success {
+                            State {
                                 map: u.map,
                                 constraints,
+                                delayed: state.delayed.clone(),
+                                tracer: state.tracer.clone(),
+                                stats: state.stats.clone(),
                                 id: state.id.clone(),
                                 depth: state.depth,
-                            })
+                            }
+                            .wake()
                         } else {
                             Stream::default()
                         }
                     }
-                    None => Stream::default(),
+                    None => {
+                        state.trace(|t| t.unify(a, b, state, false));
+                        state.record(|s| s.failures += 1);
+                        Stream::default()
+                    }
+                };
+
+                if u.sets.is_empty() {
+                    stream
+                } else {
+                    let sets = Rc::new(all(u.sets.iter().map(|(a, b)| clpset::unify(a, b))));
+                    state.trace(|t| t.made(&sets));
+                    mappend(&sets, stream)
                 }
             }
             Neq(a, b) => {
                 let mut u = Unify::new(state.map.clone());
                 match u.unify(a, b) {
-                    Some(_) if u.new.is_empty() => Stream::default(),
+                    // Sets might unify in several ways, wait until both sides are ground
+                    Some(_) if !u.sets.is_empty() => {
+                        let terms = [a.clone(), b.clone()];
+                        let goal = project_named("neq", terms.clone(), terms, |terms| {
+                            let [a, b] = terms else { unreachable!() };
+                            (a.is_ground() && b.is_ground())
+                                .then(|| if a == b { fail() } else { succeed() })
+                        });
+                        state.call_made(goal)
+                    }
+                    Some(_) if u.new.is_empty() => {
+                        state.record(|s| s.failures += 1);
+                        Stream::default()
+                    }
                     Some(_) => {
+                        state.trace(|t| t.constraint(&u.new, state));
                         let mut constraints = state.constraints.clone();
                         constraints.push(u.new);
                         Stream::new(State {
                             map: state.map.clone(),
                             constraints,
+                            delayed: state.delayed.clone(),
+                            tracer: state.tracer.clone(),
+                            stats: state.stats.clone(),
                             id: state.id.clone(),
                             depth: state.depth,
                         })
@@ -485,6 +802,28 @@ impl Goal {
             }
             Either(a, b) => append(a.call(state), b.call(state)),
             Both(a, b) => mappend(b, a.call(state)),
+            Project(terms, f, form) => match f(&state.project(terms)) {
+                Some(goal) => state.call_made(goal),
+                None => {
+                    let mut state = state.clone();
+                    state.delayed.push(Delayed {
+                        terms: terms.clone(),
+                        f: f.clone(),
+                        form: form.clone(),
+                    });
+                    Stream::new(state)
+                }
+            },
+            Relation(id, args, goal) => match &state.stats {
+                Some(stats) => {
+                    let start = Instant::now();
+                    let stream = goal.call(state);
+                    stats.borrow_mut().relation(*id, args.len(), start.elapsed());
+                    stream
+                }
+                None => goal.call(state),
+            },
+            Index(index) => index.call(state),
             Fresh(inner) => {
                 let mut inner = inner.borrow_mut();
                 if let FreshInner::Pending(func) = inner.deref() {
@@ -511,10 +850,19 @@ impl Goal {
                         let goal = goal.clone();
                         let mut state = state.clone();
                         state.depth += 1;
+                        state.trace(|t| t.suspend(&state));
+                        state.record(|s| s.thunks_created += 1);
 
                         Stream {
                             mature: Vec::new(),
-                            immature: vec![Box::new(move || goal.call(&state))],
+                            immature: vec![Box::new(move || {
+                                state.trace(|t| t.resume(&state));
+                                state.record(|s| {
+                                    s.thunks_forced += 1;
+                                    s.depth(state.depth).resumed += 1;
+                                });
+                                goal.call(&state)
+                            })],
                         }
                     }
                 }
@@ -623,18 +971,40 @@ pub struct Query<const N: usize> {
     pub stream: Stream,
     pub mature_iter: std::vec::IntoIter<State>,
     pub immature_iter: std::vec::IntoIter<Box<dyn FnOnce() -> Stream>>,
+    counters: Option<Rc<RefCell<Stats>>>,
+    tracer: Option<Tracing>,
 }
 
 impl<const N: usize> Query<N> {
+    /// Work done by the query so far, `None` unless the query was created with
+    /// `query_with_stats` or `query_traced`
+    pub fn stats(&self) -> Option<Stats> {
+        let mut stats = self.counters.as_ref()?.borrow().clone();
+        stats.fresh_vars = self.id.load(Ordering::Relaxed).into();
+        Some(stats)
+    }
+
+    fn record(&self, f: impl FnOnce(&mut Stats)) {
+        if let Some(stats) = &self.counters {
+            f(&mut stats.borrow_mut())
+        }
+    }
+
     fn pull(&mut self) -> Option<Option<State>> {
-        let state = self.mature_iter.next();
-        if state.is_some() {
-            return Some(state);
+        if let Some(Tracing(tracer)) = &self.tracer {
+            tracer.pull();
+        }
+        if let Some(state) = self.mature_iter.next() {
+            state.trace(|t| t.answer(&state));
+            self.record(|s| s.depth(state.depth).answers += 1);
+            return Some(Some(state));
         } else if !self.stream.mature.is_empty() {
             let mature = std::mem::take(&mut self.stream.mature);
             self.mature_iter = mature.into_iter();
         } else if let Some(cont) = self.immature_iter.next() {
             self.stream.append(cont());
+            let queued = self.immature_iter.len() + self.stream.immature.len();
+            self.record(|s| s.peak_immature = s.peak_immature.max(queued));
         } else if !self.stream.immature.is_empty() {
             let immature = std::mem::take(&mut self.stream.immature);
             self.immature_iter = immature.into_iter();
@@ -711,6 +1081,11 @@ pub fn purify<const N: usize>(state: &State) -> Constraints {
                     insert(set, a);
                     insert(set, b);
                 }
+                Term::Set(elements, rest) => {
+                    elements.iter().for_each(|e| insert(set, e));
+                    insert(set, rest);
+                }
+                Term::Map(entries) => entries.iter().for_each(|(_, v)| insert(set, v)),
                 Term::Var(v, _) => _ = set.insert(*v),
                 _ => (),
             }
@@ -735,6 +1110,13 @@ pub fn purify<const N: usize>(state: &State) -> Constraints {
                                     Term::Cons(a, b) => {
                                         only_reachable(a, set) && only_reachable(b, set)
                                     }
+                                    Term::Set(elements, rest) => {
+                                        elements.iter().all(|e| only_reachable(e, set))
+                                            && only_reachable(rest, set)
+                                    }
+                                    Term::Map(entries) => {
+                                        entries.iter().all(|(_, v)| only_reachable(v, set))
+                                    }
                                     Term::Var(v, _) => set.contains(v),
                                     _ => true,
                                 }
@@ -758,18 +1140,40 @@ pub fn purify<const N: usize>(state: &State) -> Constraints {
     // Convert inner HashSet to a Vec
     minimal_constraints
         .into_iter()
-        .map(|s| Vec::from_iter(s.into_iter()))
+        .map(Vec::from_iter)
         .collect()
 }
 
 impl<const N: usize> Query<N> {
-    fn iter(&mut self) -> QueryIter<N> {
+    fn iter(&mut self) -> QueryIter<'_, N> {
         QueryIter(self)
     }
 }
 
 pub fn query<const N: usize>(f: impl Binding<N>) -> Query<N> {
-    let mut state = State::default();
+    query_from(State::default(), f)
+}
+
+/// Query keeping statistics of its work, see `Query::stats`
+pub fn query_with_stats<const N: usize>(f: impl Binding<N>) -> Query<N> {
+    let state = State {
+        stats: Some(Rc::default()),
+        ..State::default()
+    };
+    query_from(state, f)
+}
+
+/// Query reporting its evaluation to `tracer`, also keeping statistics
+pub fn query_traced<const N: usize>(tracer: Rc<dyn Tracer>, f: impl Binding<N>) -> Query<N> {
+    let state = State {
+        tracer: Some(Tracing(tracer)),
+        stats: Some(Rc::default()),
+        ..State::default()
+    };
+    query_from(state, f)
+}
+
+fn query_from<const N: usize>(mut state: State, f: impl Binding<N>) -> Query<N> {
     let goal = f.bind(&mut state);
     let stream = goal.call(&state);
     Query {
@@ -778,6 +1182,8 @@ pub fn query<const N: usize>(f: impl Binding<N>) -> Query<N> {
         stream,
         mature_iter: Vec::new().into_iter(),
         immature_iter: Vec::new().into_iter(),
+        counters: state.stats.clone(),
+        tracer: state.tracer.clone(),
     }
 }
 
@@ -790,6 +1196,25 @@ impl<const N: usize> StateN<N> {
     pub fn reify(&self) -> [Term; N] {
         reify::<N>(&self.state)
     }
+
+    pub fn canonical(&self) -> Canonical<N> {
+        canonical::<N>(&self.state)
+    }
+
+    pub fn answer(&self) -> Answer {
+        self.canonical().into()
+    }
+
+    /// Reify into Rust data, each query variable converted to a `T`. Variables of different
+    /// types are reified as `Term` and converted one by one.
+    pub fn reify_as<T: FromTerm>(&self) -> Result<[T; N], FromTermError> {
+        let values = self
+            .reify()
+            .iter()
+            .map(T::from_term)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(values.try_into().unwrap_or_else(|_| unreachable!()))
+    }
 }
 
 pub fn run_all<const N: usize>(f: impl Binding<N>) -> Vec<StateN<N>> {
@@ -801,3 +1226,224 @@ pub fn run<const N: usize>(n: usize, f: impl Binding<N>) -> Vec<StateN<N>> {
     let mut q = query(f);
     q.iter().take(n).collect()
 }
+
+/// Renumbers variables in order of first appearance, starting at 0
+#[derive(Default)]
+struct Renumber {
+    map: HashMap<Var, Var>,
+}
+
+impl Renumber {
+    fn var(&mut self, v: Var) -> Var {
+        let next = Var::from_usize(self.map.len());
+        *self.map.entry(v).or_insert(next)
+    }
+
+    fn term(&mut self, term: &Term) -> Term {
+        match term {
+            Term::Var(v, t) => Term::Var(self.var(*v), *t),
+            Term::Cons(a, b) => {
+                let a = self.term(a);
+                cons(a, self.term(b))
+            }
+            Term::Set(elements, rest) => {
+                let elements: Vec<Term> = elements.iter().map(|e| self.term(e)).collect();
+                Term::set_with(elements, self.term(rest))
+            }
+            Term::Map(entries) => Term::Map(Rc::new(
+                entries.iter().map(|(k, v)| (k.clone(), self.term(v))).collect(),
+            )),
+            _ => term.clone(),
+        }
+    }
+
+    fn constraints(&mut self, constraints: &Constraints) -> Constraints {
+        constraints
+            .iter()
+            .map(|c| c.iter().map(|(v, t)| (self.var(*v), self.term(t))).collect())
+            .collect()
+    }
+}
+
+/// Reified query variables, constraints and residual goals, with variables renumbered in order
+/// of first appearance and constraints and goals sorted. Unlike `reify` and `purify` the result
+/// doesn't depend on how many variables were allocated along the way, so answers can be compared
+/// directly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canonical<const N: usize> {
+    pub terms: [Term; N],
+    pub constraints: Constraints,
+    pub delayed: Vec<DelayedForm>,
+}
+
+/// Owned answer, the canonical query variables, constraints and residual goals.
+/// With the `serde` feature it can be serialized, e.g. to JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct Answer {
+    pub terms: Vec<Term>,
+    pub constraints: Constraints,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub delayed: Vec<DelayedForm>,
+}
+
+impl<const N: usize> From<Canonical<N>> for Answer {
+    fn from(c: Canonical<N>) -> Self {
+        Self {
+            terms: c.terms.to_vec(),
+            constraints: c.constraints,
+            delayed: c.delayed,
+        }
+    }
+}
+
+fn vars(term: &Term, set: &mut HashSet<Var>) {
+    match term {
+        Term::Var(v, _) => _ = set.insert(*v),
+        Term::Cons(a, b) => {
+            vars(a, set);
+            vars(b, set);
+        }
+        Term::Set(elements, rest) => {
+            elements.iter().for_each(|e| vars(e, set));
+            vars(rest, set);
+        }
+        Term::Map(entries) => entries.iter().for_each(|(_, v)| vars(v, set)),
+        _ => (),
+    }
+}
+
+/// Goals still delayed at the end of a query, the answer only holds together with them. Goals
+/// on variables the query variables can't reach are left out like their disequalities in
+/// `purify`.
+pub fn residual<const N: usize>(state: &State) -> Vec<DelayedForm> {
+    let mut reachable = HashSet::new();
+    reify::<N>(state).iter().for_each(|t| vars(t, &mut reachable));
+
+    let mut residual: Vec<_> = state
+        .delayed
+        .iter()
+        .map(|delayed| {
+            let (name, args) = match &delayed.form {
+                Some(form) => (form.name.clone(), &form.args),
+                None => (Cow::Borrowed("project"), &delayed.terms),
+            };
+            let args = args.iter().map(|t| deep_resolve(t, &state.map)).collect();
+            DelayedForm { name, args }
+        })
+        .filter(|form| {
+            let mut used = HashSet::new();
+            form.args.iter().for_each(|t| vars(t, &mut used));
+            used.is_empty() || !used.is_disjoint(&reachable)
+        })
+        .collect();
+    residual.sort();
+    residual.dedup();
+    residual
+}
+
+/// Canonical answer of `state`, residual goals have their variables numbered after those of the
+/// terms and constraints
+pub fn canonical<const N: usize>(state: &State) -> Canonical<N> {
+    let mut renumber = Renumber::default();
+    let terms = reify::<N>(state).map(|t| renumber.term(&t));
+    let mut constraints = renumber.constraints(&purify::<N>(state));
+    for constraint in constraints.iter_mut() {
+        constraint.sort();
+    }
+    constraints.sort();
+
+    let mut delayed: Vec<_> = residual::<N>(state)
+        .into_iter()
+        .map(|DelayedForm { name, args }| DelayedForm {
+            name,
+            args: args.iter().map(|t| renumber.term(t)).collect(),
+        })
+        .collect();
+    delayed.sort();
+
+    Canonical {
+        terms,
+        constraints,
+        delayed,
+    }
+}
+
+/// Answer of a named query, the reified value of each query variable by name.
+/// Unbound variables are renumbered per answer in order of first appearance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    names: Rc<[String]>,
+    terms: Vec<Term>,
+    constraints: Constraints,
+    delayed: Vec<DelayedForm>,
+}
+
+impl Bindings {
+    fn new<const N: usize>(names: Rc<[String]>, state: &State) -> Self {
+        let Canonical {
+            terms,
+            constraints,
+            delayed,
+        } = canonical::<N>(state);
+        Self {
+            names,
+            terms: terms.to_vec(),
+            constraints,
+            delayed,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Term> {
+        let index = self.names.iter().position(|n| n == name)?;
+        Some(&self.terms[index])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Term)> {
+        self.names.iter().map(String::as_str).zip(self.terms.iter())
+    }
+
+    pub fn constraints(&self) -> &Constraints {
+        &self.constraints
+    }
+
+    /// Goals still delayed, the bindings only hold together with them
+    pub fn delayed(&self) -> &[DelayedForm] {
+        &self.delayed
+    }
+
+    pub fn into_map(self) -> HashMap<String, Term> {
+        self.names.iter().cloned().zip(self.terms).collect()
+    }
+}
+
+impl std::ops::Index<&str> for Bindings {
+    type Output = Term;
+
+    fn index(&self, name: &str) -> &Self::Output {
+        self.get(name)
+            .unwrap_or_else(|| panic!("No query variable named {name}"))
+    }
+}
+
+pub struct NamedQuery<const N: usize> {
+    pub query: Query<N>,
+    names: Rc<[String]>,
+}
+
+impl<const N: usize> Iterator for NamedQuery<N> {
+    type Item = Bindings;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let state = self.query.next()?;
+        Some(Bindings::new::<N>(self.names.clone(), &state))
+    }
+}
+
+pub fn query_named<const N: usize>(names: [&str; N], f: impl Binding<N>) -> NamedQuery<N> {
+    NamedQuery {
+        query: query(f),
+        names: names.map(str::to_string).into(),
+    }
+}
@@ -38,3 +38,556 @@ goal!(
         fresh(move |a, b, c| eq(list, list!(a, b, . c)))
     }
 );
+
+/// Peano number, zero is `()` and the successor of `n` is `(s . n)`
+pub fn peano(n: usize) -> Term {
+    (0..n).fold(NULL, |n, _| cons("s", n))
+}
+
+/// Value of a Peano number, see `peano`
+pub fn from_peano(term: &Term) -> Option<usize> {
+    let mut n = 0;
+    let mut rest = term;
+    while let Term::Cons(head, tail) = rest {
+        if **head != Term::from("s") {
+            return None;
+        }
+        n += 1;
+        rest = tail;
+    }
+    (*rest == NULL).then_some(n)
+}
+
+// `a` is less than `b`, both Peano numbers
+goal!(
+    pub fn lto(a: Var, b: Var) -> Goal {
+        fresh(move |x, y| {
+            all([
+                eq(b, cons("s", y)),
+                cond([
+                    vec![eq(a, NULL)],
+                    vec![eq(a, cons("s", x)), jield(move || lto(x, y))],
+                ]),
+            ])
+        })
+    }
+);
+
+// `a` and `b` are lists of the same length, terminates if either list has a fixed length
+goal!(
+    pub fn same_lengtho(a: Var, b: Var) -> Goal {
+        cond([
+            vec![eq(a, NULL), eq(b, NULL)],
+            vec![fresh(move |x, xs, y, ys| {
+                all([
+                    eq(a, cons(x, xs)),
+                    eq(b, cons(y, ys)),
+                    jield(move || same_lengtho(xs, ys)),
+                ])
+            })],
+        ])
+    }
+);
+
+// `n` is the length of `list` as a Peano number
+goal!(
+    pub fn lengtho(list: Var, n: Var) -> Goal {
+        cond([
+            vec![eq(list, NULL), eq(n, NULL)],
+            vec![fresh(move |head, tail, m| {
+                all([
+                    eq(list, cons(head, tail)),
+                    eq(n, cons("s", m)),
+                    jield(move || lengtho(tail, m)),
+                ])
+            })],
+        ])
+    }
+);
+
+/// `n` is the length of `list` as a number. A known `n` fixes the length, otherwise the lengths
+/// are enumerated with `lengtho` and each Peano length is read back as a number.
+pub fn num_lengtho(list: impl Into<Term>, n: impl Into<Term>) -> Goal {
+    let (list, n) = (list.into(), n.into());
+    project([n.clone()], move |terms| match &terms[0] {
+        Term::Value(k) if *k >= 0 => Some(lengtho(&list, peano(*k as usize))),
+        Term::Var(_, _) | Term::Type(_) => {
+            let (list, n) = (list.clone(), n.clone());
+            Some(fresh(move |p: Var| {
+                let n = n.clone();
+                all([
+                    lengtho(&list, p),
+                    project([p.into()], move |terms| {
+                        let k = from_peano(&terms[0])?;
+                        Some(eq(&n, i32::try_from(k).ok()?))
+                    }),
+                ])
+            }))
+        }
+        _ => Some(fail()),
+    })
+}
+
+// `r` is `list` reversed
+goal!(
+    pub fn reverseo(list: Var, r: Var) -> Goal {
+        all([same_lengtho(list, r), reverse_acc(list, NULL, r)])
+    }
+);
+
+goal!(
+    fn reverse_acc(list: Var, acc: Var, r: Var) -> Goal {
+        cond([
+            vec![eq(list, NULL), eq(acc, r)],
+            vec![fresh(move |head, tail| {
+                all([
+                    eq(list, cons(head, tail)),
+                    jield(move || reverse_acc(tail, cons(head, acc), r)),
+                ])
+            })],
+        ])
+    }
+);
+
+// `x` is the element of `list` at Peano index `n`
+goal!(
+    pub fn nth(list: Var, n: Var, x: Var) -> Goal {
+        fresh(move |head, tail, m| {
+            all([
+                eq(list, cons(head, tail)),
+                cond([
+                    vec![eq(n, NULL), eq(head, x)],
+                    vec![eq(n, cons("s", m)), jield(move || nth(tail, m, x))],
+                ]),
+            ])
+        })
+    }
+);
+
+// `x` is the last element of `list`
+goal!(
+    pub fn last(list: Var, x: Var) -> Goal {
+        fresh(move |head, tail| {
+            all([
+                eq(list, cons(head, tail)),
+                cond([
+                    vec![eq(tail, NULL), eq(head, x)],
+                    vec![not_empty(tail), jield(move || last(tail, x))],
+                ]),
+            ])
+        })
+    }
+);
+
+// `out` is `list` without any occurrence of `x`
+goal!(
+    pub fn removeo(list: Var, x: Var, out: Var) -> Goal {
+        cond([
+            vec![eq(list, NULL), eq(out, NULL)],
+            vec![fresh(move |tail| {
+                all([
+                    eq(list, cons(x, tail)),
+                    jield(move || removeo(tail, x, out)),
+                ])
+            })],
+            vec![fresh(move |head, tail, rest| {
+                all([
+                    eq(list, cons(head, tail)),
+                    neq(head, x),
+                    eq(out, cons(head, rest)),
+                    jield(move || removeo(tail, x, rest)),
+                ])
+            })],
+        ])
+    }
+);
+
+// `rest` is `list` with one occurrence of `x` taken out
+goal!(
+    pub fn selecto(list: Var, x: Var, rest: Var) -> Goal {
+        fresh(move |head, tail, r| {
+            all([
+                eq(list, cons(head, tail)),
+                cond([
+                    vec![eq(head, x), eq(rest, tail)],
+                    vec![eq(rest, cons(head, r)), jield(move || selecto(tail, x, r))],
+                ]),
+            ])
+        })
+    }
+);
+
+// `p` is a permutation of `list`
+goal!(
+    pub fn permuteo(list: Var, p: Var) -> Goal {
+        all([same_lengtho(list, p), permute(list, p)])
+    }
+);
+
+goal!(
+    fn permute(list: Var, p: Var) -> Goal {
+        cond([
+            vec![eq(list, NULL), eq(p, NULL)],
+            vec![fresh(move |head, tail, rest| {
+                all([
+                    eq(p, cons(head, tail)),
+                    selecto(list, head, rest),
+                    jield(move || permute(rest, tail)),
+                ])
+            })],
+        ])
+    }
+);
+
+// `pairs` is the list of pairs `(a . b)` of the elements of `a` and `b`
+goal!(
+    pub fn zipo(a: Var, b: Var, pairs: Var) -> Goal {
+        cond([
+            vec![eq(a, NULL), eq(b, NULL), eq(pairs, NULL)],
+            vec![fresh(move |x, xs, y, ys, rest| {
+                all([
+                    eq(a, cons(x, xs)),
+                    eq(b, cons(y, ys)),
+                    eq(pairs, cons(cons(x, y), rest)),
+                    jield(move || zipo(xs, ys, rest)),
+                ])
+            })],
+        ])
+    }
+);
+
+/// Relation between two terms, e.g. an element and its image in `mapo`
+pub type Relation2 = Rc<dyn Fn(Var, Var) -> Goal>;
+
+/// Relation between three terms, e.g. an element, an accumulator and the next accumulator in `foldo`
+pub type Relation3 = Rc<dyn Fn(Var, Var, Var) -> Goal>;
+
+/// `rel` holds between each element of `a` and the element of `b` at the same position
+pub fn mapo(
+    rel: impl Fn(Var, Var) -> Goal + 'static,
+    a: impl Into<Term>,
+    b: impl Into<Term>,
+) -> Goal {
+    let (a, b) = (a.into(), b.into());
+    let rel: Relation2 = Rc::new(rel);
+    fresh(move |x, y| all([eq(&a, x), eq(&b, y), map_inner(rel.clone(), x, y)]))
+}
+
+fn map_inner(rel: Relation2, a: Var, b: Var) -> Goal {
+    cond([
+        vec![eq(a, NULL), eq(b, NULL)],
+        vec![fresh(move |x, xs, y, ys| {
+            let rel = rel.clone();
+            all([
+                eq(a, cons(x, xs)),
+                eq(b, cons(y, ys)),
+                rel(x, y),
+                jield(move || map_inner(rel.clone(), xs, ys)),
+            ])
+        })],
+    ])
+}
+
+/// Left fold of `rel` over `list`, `rel(element, acc, next)` relates an element and the
+/// accumulator to the next accumulator. `out` is the final accumulator.
+pub fn foldo(
+    rel: impl Fn(Var, Var, Var) -> Goal + 'static,
+    list: impl Into<Term>,
+    acc: impl Into<Term>,
+    out: impl Into<Term>,
+) -> Goal {
+    let (list, acc, out) = (list.into(), acc.into(), out.into());
+    let rel: Relation3 = Rc::new(rel);
+    fresh(move |l, a, o| {
+        all([
+            eq(&list, l),
+            eq(&acc, a),
+            eq(&out, o),
+            fold_inner(rel.clone(), l, a, o),
+        ])
+    })
+}
+
+fn fold_inner(rel: Relation3, list: Var, acc: Var, out: Var) -> Goal {
+    cond([
+        vec![eq(list, NULL), eq(acc, out)],
+        vec![fresh(move |head, tail, next| {
+            let rel = rel.clone();
+            all([
+                eq(list, cons(head, tail)),
+                rel(head, acc, next),
+                jield(move || fold_inner(rel.clone(), tail, next, out)),
+            ])
+        })],
+    ])
+}
+
+// `out` is the list of numbers and strings in the nested list `tree`, from left to right
+goal!(
+    pub fn flatteno(tree: Var, out: Var) -> Goal {
+        cond([
+            vec![eq(tree, NULL), eq(out, NULL)],
+            vec![num(tree), eq(out, list!(tree))],
+            vec![str(tree), eq(out, list!(tree))],
+            vec![fresh(move |head, tail, a, b| {
+                all([
+                    eq(tree, cons(head, tail)),
+                    jield(move || flatteno(head, a)),
+                    jield(move || flatteno(tail, b)),
+                    append(a, b, out),
+                ])
+            })],
+        ])
+    }
+);
+
+/// `sorted` is `list` sorted by the strict total order `lt`, keeping duplicates
+pub fn sorto(
+    lt: impl Fn(Var, Var) -> Goal + 'static,
+    list: impl Into<Term>,
+    sorted: impl Into<Term>,
+) -> Goal {
+    let (list, sorted) = (list.into(), sorted.into());
+    let lt: Relation2 = Rc::new(lt);
+    fresh(move |l, s| {
+        all([
+            eq(&list, l),
+            eq(&sorted, s),
+            same_lengtho(l, s),
+            sort_inner(lt.clone(), l, s),
+        ])
+    })
+}
+
+fn sort_inner(lt: Relation2, list: Var, sorted: Var) -> Goal {
+    cond([
+        vec![eq(list, NULL), eq(sorted, NULL)],
+        vec![fresh(move |head, tail, s| {
+            let lt = lt.clone();
+            let lt2 = lt.clone();
+            all([
+                eq(list, cons(head, tail)),
+                jield(move || sort_inner(lt.clone(), tail, s)),
+                insert(lt2, head, s, sorted),
+            ])
+        })],
+    ])
+}
+
+// Insert `x` into the sorted list `list`
+fn insert(lt: Relation2, x: Var, list: Var, out: Var) -> Goal {
+    fresh(move |head, tail, rest| {
+        let lt = lt.clone();
+        cond([
+            vec![eq(list, NULL), eq(out, list!(x))],
+            vec![
+                eq(list, cons(head, tail)),
+                eq(out, cons(x, list)),
+                either(eq(x, head), lt(x, head)),
+            ],
+            vec![
+                eq(list, cons(head, tail)),
+                eq(out, cons(head, rest)),
+                lt(head, x),
+                jield(move || insert(lt.clone(), x, tail, rest)),
+            ],
+        ])
+    })
+}
+
+// `x` isn't an element of `list`
+goal!(
+    pub fn not_containso(list: Var, x: Var) -> Goal {
+        cond([
+            vec![eq(list, NULL)],
+            vec![fresh(move |head, tail| {
+                all([
+                    eq(list, cons(head, tail)),
+                    neq(head, x),
+                    jield(move || not_containso(tail, x)),
+                ])
+            })],
+        ])
+    }
+);
+
+// The elements of `list` are pairwise distinct
+goal!(
+    pub fn distincto(list: Var) -> Goal {
+        cond([
+            vec![eq(list, NULL)],
+            vec![fresh(move |head, tail| {
+                all([
+                    eq(list, cons(head, tail)),
+                    not_containso(tail, head),
+                    jield(move || distincto(tail)),
+                ])
+            })],
+        ])
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    fn answers<const N: usize>(result: Vec<StateN<N>>) -> String {
+        let result: Vec<_> = result.iter().map(StateN::canonical).collect();
+        AsScheme(result).to_string()
+    }
+
+    #[test]
+    fn length() {
+        let result = run_all(|n| lengtho(list!(1, 2, 3), n));
+        assert_eq!(from_peano(&result[0].reify()[0]), Some(3));
+
+        let result = run_all(|l| lengtho(l, peano(2)));
+        assert_eq!(answers(result), "(((_0 _1)))");
+
+        let result = run(3, lengtho);
+        assert_eq!(answers(result), "((() ()) ((_0) (s)) ((_0 _1) (s s)))");
+
+        let result = run_all(|a| lto(a, peano(2)));
+        assert_eq!(answers(result), "((()) ((s)))");
+
+        let result = run(2, |b| lto(peano(1), b));
+        assert_eq!(answers(result), "(((s s . _0)))");
+    }
+
+    #[test]
+    fn num_length() {
+        let result = run_all(|n| num_lengtho(list!(1, 2, 3), n));
+        assert_eq!(answers(result), "((3))");
+
+        let result = run_all(|l| num_lengtho(l, 2));
+        assert_eq!(answers(result), "(((_0 _1)))");
+
+        let result = run(3, num_lengtho);
+        assert_eq!(answers(result), "((() 0) ((_0) 1) ((_0 _1) 2))");
+
+        let result = run_all(|n| all([num(n), num_lengtho(list!(1), n)]));
+        assert_eq!(answers(result), "((1))");
+
+        assert!(run_all(|| num_lengtho(list!(1), -1)).is_empty());
+        assert!(run_all(|| num_lengtho(list!(1), "one")).is_empty());
+    }
+
+    #[test]
+    fn reverse() {
+        let result = run_all(|r| reverseo(list!(1, 2, 3), r));
+        assert_eq!(answers(result), "(((3 2 1)))");
+
+        let result = run_all(|l| reverseo(l, list!(1, 2, 3)));
+        assert_eq!(answers(result), "(((3 2 1)))");
+
+        let result = run(3, reverseo);
+        assert_eq!(answers(result), "((() ()) ((_0) (_0)) ((_0 _1) (_1 _0)))");
+    }
+
+    #[test]
+    fn nth_and_last() {
+        let result = run_all(|x| nth(list!(1, 2, 3), peano(1), x));
+        assert_eq!(answers(result), "((2))");
+
+        let result = run_all(|n| nth(list!(1, 2, 1), n, 1));
+        assert_eq!(answers(result), "((()) ((s s)))");
+
+        let result = run_all(|x| last(list!(1, 2, 3), x));
+        assert_eq!(answers(result), "((3))");
+
+        let result = run(2, |l| last(l, 3));
+        assert_eq!(answers(result), "(((3)) ((_0 3)))");
+    }
+
+    #[test]
+    fn remove_and_select() {
+        let result = run_all(|out| removeo(list!(1, 2, 3, 2), 2, out));
+        assert_eq!(answers(result), "(((1 3)))");
+
+        let result = run_all(|x| removeo(list!(1, 2), x, list!(1)));
+        assert_eq!(answers(result), "((2))");
+
+        let result = run_all(|x, rest| selecto(list!(1, 2, 3), x, rest));
+        assert_eq!(answers(result), "((1 (2 3)) (2 (1 3)) (3 (1 2)))");
+
+        let result = run_all(|l| selecto(l, 0, list!(1, 2)));
+        assert_eq!(answers(result), "(((0 1 2)) ((1 0 2)) ((1 2 0)))");
+    }
+
+    #[test]
+    fn permute() {
+        let result = run_all(|p| permuteo(list!(1, 2, 3), p));
+        assert_eq!(
+            answers(result),
+            "(((1 2 3)) ((1 3 2)) ((2 1 3)) ((2 3 1)) ((3 1 2)) ((3 2 1)))"
+        );
+
+        let result = run_all(|l| permuteo(l, list!(1, 2)));
+        assert_eq!(answers(result), "(((1 2)) ((2 1)))");
+    }
+
+    #[test]
+    fn zip() {
+        let result = run_all(|pairs| zipo(list!(1, 2), list!("a", "b"), pairs));
+        assert_eq!(answers(result), "((((1 . a) (2 . b))))");
+
+        let result = run_all(|a, b| zipo(a, b, list!(cons(1, "a"), cons(2, "b"))));
+        assert_eq!(answers(result), "(((1 2) (a b)))");
+    }
+
+    #[test]
+    fn map_and_fold() {
+        let succ = |a, b| eq(b, cons("s", a));
+
+        let result = run_all(|b| mapo(succ, list!(NULL, peano(1)), b));
+        assert_eq!(answers(result), "((((s) (s s))))");
+
+        let result = run_all(|a| mapo(succ, a, list!(peano(1), peano(3))));
+        assert_eq!(answers(result), "(((() (s s))))");
+
+        // Sum of Peano numbers
+        let add = |x, acc, next| append(x, acc, next);
+        let result = run_all(|sum| foldo(add, list!(peano(1), peano(2)), NULL, sum));
+        assert_eq!(from_peano(&result[0].reify()[0]), Some(3));
+
+        let result = run(1, |x| foldo(add, list!(peano(1), x), NULL, peano(3)));
+        assert_eq!(answers(result), "(((s s)))");
+    }
+
+    #[test]
+    fn flatten() {
+        let result = run_all(|out| flatteno(list!(1, list!(2, list!()), list!(list!("a"))), out));
+        assert_eq!(answers(result), "(((1 2 a)))");
+
+        let result = run(3, |tree| flatteno(tree, list!(1)));
+        assert_eq!(answers(result), "((1) ((() . 1)) ((1)))");
+    }
+
+    #[test]
+    fn sort() {
+        let result = run_all(|s| sorto(lto, list!(peano(2), peano(0), peano(1), peano(0)), s));
+        assert_eq!(answers(result), "(((() () (s) (s s))))");
+
+        let result = run_all(|l| sorto(lto, l, list!(peano(0), peano(1))));
+        assert_eq!(answers(result), "(((() (s))) (((s) ())))");
+
+        let result = run_all(|l| sorto(lto, l, list!(peano(1), peano(0))));
+        assert_eq!(answers(result), "()");
+    }
+
+    #[test]
+    fn distinct() {
+        assert_eq!(run_all(|| distincto(list!(1, 2, 3))).len(), 1);
+        assert_eq!(run_all(|| distincto(list!(1, 2, 1))).len(), 0);
+
+        let result = run_all(|x| all([distincto(list!(1, x)), eq(x, 1)]));
+        assert_eq!(answers(result), "()");
+
+        let result = run_all(|x, y| distincto(list!(x, y, 1)));
+        assert_eq!(
+            answers(result),
+            "((_0 _1) : (((_0 . 1)) ((_1 . 1)) ((_1 . _0))))"
+        );
+    }
+}
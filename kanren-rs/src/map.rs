@@ -0,0 +1,229 @@
+// Finite maps
+//
+// A map is an association list of `(key . value)` pairs with distinct keys, like the environments
+// of an interpreter. Keys are kept distinct with `neq` constraints, so `extendo` with a key that
+// is still unbound leaves the constraint that it differs from the existing keys.
+//
+// Closed records with ground keys can also be written as native `Term::Map` terms, which unify
+// when they have the same keys and their values unify. `recordo` relates the two forms.
+
+use std::collections::BTreeMap;
+
+use crate::goal;
+use crate::*;
+
+impl Term {
+    /// Closed record of `entries`, later entries replace earlier ones. Panics when a key isn't
+    /// ground.
+    pub fn map<K: Into<Term>, V: Into<Term>>(entries: impl IntoIterator<Item = (K, V)>) -> Term {
+        let entries: BTreeMap<Term, Term> = entries
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        assert!(
+            entries.keys().all(Term::is_ground),
+            "record keys aren't ground"
+        );
+        Term::Map(Rc::new(entries.into_iter().collect()))
+    }
+}
+
+// `value` is stored under `key` in `map`
+goal!(
+    pub fn lookupo(map: Var, key: Var, value: Var) -> Goal {
+        fresh(move |k, v, rest| {
+            cond([
+                vec![eq(map, cons(cons(key, value), rest))],
+                vec![
+                    eq(map, cons(cons(k, v), rest)),
+                    neq(k, key),
+                    jield(move || lookupo(rest, key, value)),
+                ],
+            ])
+        })
+    }
+);
+
+// `key` isn't a key of `map`
+goal!(
+    pub fn not_keyo(map: Var, key: Var) -> Goal {
+        fresh(move |k, v, rest| {
+            cond([
+                vec![eq(map, NULL)],
+                vec![
+                    eq(map, cons(cons(k, v), rest)),
+                    neq(k, key),
+                    jield(move || not_keyo(rest, key)),
+                ],
+            ])
+        })
+    }
+);
+
+// The keys of `map` are distinct
+goal!(
+    pub fn distinct_keyso(map: Var) -> Goal {
+        fresh(move |k, v, rest| {
+            cond([
+                vec![eq(map, NULL)],
+                vec![
+                    eq(map, cons(cons(k, v), rest)),
+                    not_keyo(rest, k),
+                    jield(move || distinct_keyso(rest)),
+                ],
+            ])
+        })
+    }
+);
+
+// `result` is `map` with `value` stored under the new key `key`
+goal!(
+    pub fn extendo(map: Var, key: Var, value: Var, result: Var) -> Goal {
+        all([not_keyo(map, key), eq(result, cons(cons(key, value), map))])
+    }
+);
+
+// `result` is `map` without the entry for `key`
+goal!(
+    pub fn removeo(map: Var, key: Var, result: Var) -> Goal {
+        fresh(move |k, v, rest, c| {
+            cond([
+                vec![eq(map, NULL), eq(result, NULL)],
+                vec![eq(map, cons(cons(key, v), rest)), eq(result, rest)],
+                vec![
+                    eq(map, cons(cons(k, v), rest)),
+                    neq(k, key),
+                    eq(result, cons(cons(k, v), c)),
+                    jield(move || removeo(rest, key, c)),
+                ],
+            ])
+        })
+    }
+);
+
+// `keys` are the keys of `map` in order
+goal!(
+    pub fn keyso(map: Var, keys: Var) -> Goal {
+        fresh(move |k, v, rest, ks| {
+            cond([
+                vec![eq(map, NULL), eq(keys, NULL)],
+                vec![
+                    eq(map, cons(cons(k, v), rest)),
+                    eq(keys, cons(k, ks)),
+                    jield(move || keyso(rest, ks)),
+                ],
+            ])
+        })
+    }
+);
+
+/// `map` is the association list of the record `record`, ordered by key. Waits until the record
+/// or the keys of the list are bound
+pub fn recordo(record: impl Into<Term>, map: impl Into<Term>) -> Goal {
+    project([record.into(), map.into()], |terms| {
+        let [record, map] = terms else { unreachable!() };
+        match record {
+            Term::Map(entries) => {
+                let pairs = entries
+                    .iter()
+                    .rev()
+                    .fold(NULL, |list, (k, v)| cons(cons(k, v), list));
+                return Some(eq(map, pairs));
+            }
+            Term::Var(_, _) => {}
+            _ => return Some(fail()),
+        }
+
+        let mut entries = Vec::new();
+        for pair in map.to_vec()? {
+            match pair {
+                Term::Cons(k, v) if k.is_ground() => {
+                    entries.push((k.as_ref().clone(), v.as_ref().clone()))
+                }
+                Term::Cons(_, _) | Term::Var(_, _) => return None,
+                _ => return Some(fail()),
+            }
+        }
+        let len = entries.len();
+        let result = Term::map(entries);
+        match &result {
+            Term::Map(unique) if unique.len() == len => Some(eq(record, result)),
+            _ => Some(fail()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    fn answers<const N: usize>(result: Vec<StateN<N>>) -> String {
+        let result: Vec<_> = result.iter().map(StateN::canonical).collect();
+        AsScheme(result).to_string()
+    }
+
+    #[test]
+    fn alist() {
+        let env = || list!(cons("x", 1), cons("y", 2));
+
+        let result = run_all(|v| lookupo(env(), "y", v));
+        assert_eq!(answers(result), "((2))");
+
+        let result = run_all(|k| lookupo(env(), k, 2));
+        assert_eq!(answers(result), "((y))");
+
+        let result = run_all(|m| extendo(env(), "x", 3, m));
+        assert_eq!(answers(result), "()");
+
+        let result = run_all(|m| extendo(env(), "z", 3, m));
+        assert_eq!(answers(result), "((((z . 3) (x . 1) (y . 2))))");
+
+        let result = run_all(|m| removeo(env(), "x", m));
+        assert_eq!(answers(result), "((((y . 2))))");
+
+        let result = run_all(|ks| keyso(env(), ks));
+        assert_eq!(answers(result), "(((x y)))");
+    }
+
+    #[test]
+    fn unique_keys() {
+        // The new key has to differ from the existing ones
+        let result = run_all(|k, m| extendo(list!(cons("x", 1)), k, 2, m));
+        assert_eq!(answers(result), "((_0 ((_0 . 2) (x . 1))) : (((_0 . x))))");
+
+        let result =
+            run_all(|k| all([distinct_keyso(list!(cons("a", 1), cons(k, 2))), eq(k, "a")]));
+        assert_eq!(answers(result), "()");
+    }
+
+    #[test]
+    fn record() {
+        let result = run_all(|x, y| {
+            eq(
+                Term::map([("a", Term::from(x)), ("b", 2.into())]),
+                Term::map([("b", Term::from(y)), ("a", 1.into())]),
+            )
+        });
+        assert_eq!(answers(result), "((1 2))");
+
+        let result = run_all(|| eq(Term::map([("a", 1)]), Term::map([("b", 1)])));
+        assert_eq!(result.len(), 0);
+
+        let result = run_all(|m| recordo(Term::map([("b", 2), ("a", 1)]), m));
+        assert_eq!(answers(result), "((((a . 1) (b . 2))))");
+
+        let result = run_all(|r, v| all([recordo(r, list!(cons("b", v), cons("a", 1))), eq(v, 2)]));
+        assert_eq!(answers(result), "((#hash((a . 1) (b . 2)) 2))");
+
+        // Constraints on variables only reachable through a record
+        let result = run_all(|q| fresh(move |x| all([eq(q, Term::map([("a", x)])), neq(x, 1)])));
+        assert_eq!(answers(result), "((#hash((a . _0))) : (((_0 . 1))))");
+    }
+
+    #[test]
+    #[should_panic(expected = "record keys aren't ground")]
+    fn unbound_key() {
+        Term::map([(Var(0), 1)]);
+    }
+}
@@ -0,0 +1,315 @@
+// Goal optimizer
+//
+// `optimize` rewrites a goal before it runs, without changing its answers:
+//
+// - nested conjunctions and disjunctions are flattened
+// - `eq` of two ground terms is decided, failing conjunctions and disjunction branches are
+//   dropped
+// - cheap `eq` goals, with one side ground and no sets, run first in a conjunction
+// - `eq` goals shared by all branches of a disjunction are hoisted out of it, like the common
+//   unification of the clauses of a `cond`
+// - disjunctions of clauses unifying a variable with different ground terms are indexed on it, see
+//   `index`
+//
+// Goals made later by `fresh`, `jield` and `project` are optimized when they are made. Goals
+// shared with another goal are left as they are. The order of answers can change.
+
+use std::cell::RefCell;
+
+use crate::*;
+
+/// Optimized `goal`, see the module documentation
+pub fn optimize(goal: Goal) -> Goal {
+    match goal {
+        Goal::Eq(a, b) => match decide(&a, &b) {
+            Some(true) => succeed(),
+            Some(false) => fail(),
+            None => Goal::Eq(a, b),
+        },
+        Goal::Both(_, _) => conjunction(goal),
+        Goal::Either(_, _) => disjunction(goal),
+        Goal::Fresh(inner) => match inner.into_inner() {
+            FreshInner::Pending(f) => {
+                Goal::Fresh(RefCell::new(FreshInner::Pending(Rc::new(move |state| {
+                    optimize(f(state))
+                }))))
+            }
+            resolved => Goal::Fresh(RefCell::new(resolved)),
+        },
+        Goal::Yield(inner) => match inner.into_inner() {
+            YieldInner::Pending(f) => jield(move || optimize(f())),
+            resolved => Goal::Yield(RefCell::new(resolved)),
+        },
+        Goal::Project(terms, f, form) => {
+            Goal::Project(terms, Rc::new(move |terms| f(terms).map(optimize)), form)
+        }
+        Goal::Relation(name, args, goal) => Goal::Relation(name, args, optimize_rc(goal)),
+        Goal::Neq(_, _) | Goal::Index(_) => goal,
+    }
+}
+
+fn optimize_rc(goal: Rc<Goal>) -> Rc<Goal> {
+    match Rc::try_unwrap(goal) {
+        Ok(goal) => Rc::new(optimize(goal)),
+        Err(goal) => goal,
+    }
+}
+
+// Only lists of numbers and strings, sets and maps unify with terms that differ
+pub(crate) fn plain(term: &Term) -> bool {
+    match term {
+        Term::Value(_) | Term::String(_) | Term::Null => true,
+        Term::Cons(a, b) => plain(a) && plain(b),
+        _ => false,
+    }
+}
+
+fn has_set(term: &Term) -> bool {
+    match term {
+        Term::Set(_, _) => true,
+        Term::Cons(a, b) => has_set(a) || has_set(b),
+        Term::Map(entries) => entries.iter().any(|(_, v)| has_set(v)),
+        _ => false,
+    }
+}
+
+/// Whether `a` and `b` unify, when that is known without a substitution
+fn decide(a: &Term, b: &Term) -> Option<bool> {
+    (plain(a) && plain(b)).then(|| a == b)
+}
+
+fn is_fail(goal: &Goal) -> bool {
+    matches!(goal, Goal::Eq(a, b) if decide(a, b) == Some(false))
+}
+
+fn is_succeed(goal: &Goal) -> bool {
+    matches!(goal, Goal::Eq(a, b) if decide(a, b) == Some(true))
+}
+
+// Binds at most once without suspending, so it is best run before the rest
+fn is_cheap(goal: &Goal) -> bool {
+    matches!(goal, Goal::Eq(a, b)
+        if (a.is_ground() || b.is_ground()) && !has_set(a) && !has_set(b))
+}
+
+/// Goals of nested conjunctions, or disjunctions for `disjunction`, in order
+fn flatten(goal: Rc<Goal>, disjunction: bool, out: &mut Vec<Rc<Goal>>) {
+    match goal.as_ref() {
+        Goal::Both(a, b) if !disjunction => {
+            let (a, b) = (a.clone(), b.clone());
+            drop(goal);
+            flatten(a, disjunction, out);
+            flatten(b, disjunction, out);
+        }
+        Goal::Either(a, b) if disjunction => {
+            let (a, b) = (a.clone(), b.clone());
+            drop(goal);
+            flatten(a, disjunction, out);
+            flatten(b, disjunction, out);
+        }
+        _ => out.push(goal),
+    }
+}
+
+fn rebuild(mut goals: Vec<Rc<Goal>>, f: fn(Rc<Goal>, Rc<Goal>) -> Goal) -> Rc<Goal> {
+    let last = goals.pop().expect("at least one goal");
+    goals.into_iter().rev().fold(last, |b, a| Rc::new(f(a, b)))
+}
+
+fn into_goal(goal: Rc<Goal>) -> Goal {
+    // A goal shared with another goal can't be taken out of its `Rc`, so it is wrapped
+    Rc::try_unwrap(goal).unwrap_or_else(|goal| Goal::Both(goal, Rc::new(succeed())))
+}
+
+fn conjunction(goal: Goal) -> Goal {
+    let mut goals = Vec::new();
+    flatten(Rc::new(goal), false, &mut goals);
+
+    // Optimizing a disjunction can give a conjunction, so flatten again
+    let mut optimized = Vec::new();
+    for goal in goals {
+        flatten(optimize_rc(goal), false, &mut optimized);
+    }
+    let mut goals: Vec<_> = optimized
+        .into_iter()
+        .filter(|goal| !is_succeed(goal))
+        .collect();
+    if goals.iter().any(|goal| is_fail(goal)) {
+        return fail();
+    }
+    if goals.is_empty() {
+        return succeed();
+    }
+
+    // Stable, so the other goals keep their order
+    goals.sort_by_key(|goal| !is_cheap(goal));
+    into_goal(rebuild(goals, Goal::Both))
+}
+
+fn disjunction(goal: Goal) -> Goal {
+    let mut branches = Vec::new();
+    flatten(Rc::new(goal), true, &mut branches);
+
+    let branches: Vec<_> = branches
+        .into_iter()
+        .map(optimize_rc)
+        .filter(|goal| !is_fail(goal))
+        .collect();
+    if branches.is_empty() {
+        return fail();
+    }
+    if branches.len() == 1 {
+        return into_goal(branches.into_iter().next().unwrap());
+    }
+
+    match hoist(branches) {
+        Ok((shared, branches)) => {
+            let branches = either(branches);
+            let goals = shared.into_iter().map(Rc::new).chain([branches]).collect();
+            into_goal(rebuild(goals, Goal::Both))
+        }
+        Err(branches) => into_goal(either(branches)),
+    }
+}
+
+fn either(branches: Vec<Rc<Goal>>) -> Rc<Goal> {
+    match index::index(branches) {
+        Ok(goal) => Rc::new(goal),
+        Err(branches) => rebuild(branches, Goal::Either),
+    }
+}
+
+/// `eq` goals in all `branches` and the branches without them, or the branches unchanged when
+/// there are none
+#[allow(clippy::type_complexity)]
+fn hoist(branches: Vec<Rc<Goal>>) -> Result<(Vec<Goal>, Vec<Rc<Goal>>), Vec<Rc<Goal>>> {
+    let mut conjunctions = Vec::new();
+    for branch in &branches {
+        let mut goals = Vec::new();
+        flatten(branch.clone(), false, &mut goals);
+        conjunctions.push(goals);
+    }
+
+    let same =
+        |x: &Goal, y: &Goal| matches!((x, y), (Goal::Eq(a, b), Goal::Eq(c, d)) if a == c && b == d);
+    let shared: Vec<(Term, Term)> = conjunctions[0]
+        .iter()
+        .filter(|goal| {
+            conjunctions[1..]
+                .iter()
+                .all(|c| c.iter().any(|g| same(g, goal)))
+        })
+        .filter_map(|goal| match goal.as_ref() {
+            Goal::Eq(a, b) => Some((a.clone(), b.clone())),
+            _ => None,
+        })
+        .collect();
+    if shared.is_empty() {
+        return Err(branches);
+    }
+    drop(branches);
+
+    let branches = conjunctions
+        .into_iter()
+        .map(|mut goals| {
+            for (a, b) in &shared {
+                let shared = Goal::Eq(a.clone(), b.clone());
+                if let Some(i) = goals.iter().position(|g| same(g, &shared)) {
+                    goals.remove(i);
+                }
+            }
+            if goals.is_empty() {
+                Rc::new(succeed())
+            } else {
+                rebuild(goals, Goal::Both)
+            }
+        })
+        .collect();
+    let shared = shared.into_iter().map(|(a, b)| Goal::Eq(a, b)).collect();
+    Ok((shared, branches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::GoalTree;
+
+    fn tree(goal: &Goal) -> String {
+        GoalTree(goal).to_string()
+    }
+
+    #[test]
+    fn flatten_and_decide() {
+        let x = Var(0);
+        let y = Var(1);
+        let goal = all([all([eq(x, 1), eq(y, 2)]), eq(x, y), eq(1, 1)]);
+        assert_eq!(
+            tree(&optimize(goal)),
+            tree(&all([eq(x, 1), eq(y, 2), eq(x, y)]))
+        );
+
+        let goal = any([all([eq(1, 2), eq(x, 1)]), any([eq(x, 3), eq("a", "b")])]);
+        assert_eq!(tree(&optimize(goal)), tree(&eq(x, 3)));
+        assert!(is_fail(&optimize(all([eq(x, 1), eq(list!(1), list!(2))]))));
+
+        // Sets and types unify with terms that differ
+        let set = Term::set_with([&Term::from(1), &Term::from(2)], NULL);
+        assert!(!is_fail(&optimize(eq(
+            set,
+            Term::set_with([&Term::from(2)], Term::set_with([&Term::from(1)], NULL))
+        ))));
+        assert!(!is_fail(&optimize(eq(NUM, 1))));
+    }
+
+    #[test]
+    fn reorder_and_hoist() {
+        let x = Var(0);
+        let y = Var(1);
+        let goal = all([neq(x, 2), jield(move || eq(y, 1)), eq(x, 1)]);
+        let expected = "Both\n Var(Var(0), Any) == Value(1)\n Both\n  Var(Var(0), Any) != Value(2)\n  Yield\n   -\n";
+        assert_eq!(tree(&optimize(goal)), expected);
+
+        let goal = cond([
+            vec![eq(x, NULL), neq(y, 0), eq(y, list!(x))],
+            vec![eq(x, list!(1)), eq(y, list!(x))],
+            vec![eq(y, list!(x)), eq(x, list!(2))],
+        ]);
+        let clauses = vec![
+            Rc::new(all([eq(x, NULL), neq(y, 0)])),
+            Rc::new(eq(x, list!(1))),
+            Rc::new(eq(x, list!(2))),
+        ];
+        let expected = all([eq(y, list!(x)), index::index(clauses).ok().unwrap()]);
+        assert_eq!(tree(&optimize(goal)), tree(&expected));
+    }
+
+    #[test]
+    fn same_answers() {
+        fn sorted<const N: usize>(states: Vec<StateN<N>>) -> Vec<String> {
+            let mut answers: Vec<_> = states
+                .iter()
+                .map(|s| crate::display::AsScheme(s.canonical()).to_string())
+                .collect();
+            answers.sort();
+            answers
+        }
+
+        let plain = sorted(run_all(|x, y| list::append(x, y, list!(1, 2, 3))));
+        let optimized = sorted(run_all(|x, y| optimize(list::append(x, y, list!(1, 2, 3)))));
+        assert_eq!(plain.len(), 4);
+        assert_eq!(plain, optimized);
+
+        let expr = ski::parse("S(KS)K");
+        let plain = sorted(run(1, |x| ski::eval(&expr, x, list!("c", "b", "a"))));
+        let optimized = sorted(run(1, |x| {
+            optimize(ski::eval(&expr, x, list!("c", "b", "a")))
+        }));
+        assert_eq!(plain, optimized);
+
+        let program = list!("list", list!("quote", 1), list!("quote", "a"));
+        let plain = sorted(run(1, |x| interp::evalo(&program, x)));
+        let optimized = sorted(run(1, |x| optimize(interp::evalo(&program, x))));
+        assert_eq!(plain, vec!["((1 a))"]);
+        assert_eq!(plain, optimized);
+    }
+}
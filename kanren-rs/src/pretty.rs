@@ -0,0 +1,734 @@
+// Pretty printing in Scheme or Prolog syntax
+//
+// Terms and answers are laid out as groups like lists, written on one line when they fit in the
+// line width and otherwise with one element per line, indented. With an unlimited width the
+// Scheme syntax is the same as `AsScheme`. `AsProlog` writes one line in Prolog syntax, like
+// `X = [1,2|_A], dif(X, 5)`.
+
+use std::fmt::Display;
+
+use crate::{Bindings, Term, TermType, Var};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Scheme,
+    Prolog,
+}
+
+// Private module, so the layout isn't part of the public interface
+mod sealed {
+    /// How a group is written when it doesn't fit on a line
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Break {
+        /// Lisp style, the first element and the closing bracket stay with the others
+        Hug,
+        /// Brackets on their own lines
+        Block,
+        /// Elements on their own lines without indenting, like the bindings of an answer
+        Lines,
+    }
+
+    #[derive(Debug, Clone)]
+    pub enum Doc {
+        Text(String),
+        Concat(Vec<Doc>),
+        Group {
+            open: &'static str,
+            items: Vec<Doc>,
+            sep: &'static str,
+            /// Improper tail with its separator, like `. x` or `|X`
+            tail: Option<(&'static str, Box<Doc>)>,
+            close: &'static str,
+            style: Break,
+        },
+    }
+
+    pub trait ToDoc {
+        fn layout(&self, syntax: super::Syntax) -> Doc;
+    }
+}
+
+use sealed::{Break, Doc, ToDoc};
+
+impl Doc {
+    fn text(s: impl Into<String>) -> Self {
+        Doc::Text(s.into())
+    }
+
+    fn group(
+        open: &'static str,
+        items: Vec<Doc>,
+        sep: &'static str,
+        close: &'static str,
+        style: Break,
+    ) -> Self {
+        Doc::Group {
+            open,
+            items,
+            sep,
+            tail: None,
+            close,
+            style,
+        }
+    }
+
+    fn flat(&self, out: &mut String) {
+        match self {
+            Doc::Text(s) => out.push_str(s),
+            Doc::Concat(docs) => docs.iter().for_each(|d| d.flat(out)),
+            Doc::Group {
+                open,
+                items,
+                sep,
+                tail,
+                close,
+                ..
+            } => {
+                out.push_str(open);
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        out.push_str(sep);
+                    }
+                    item.flat(out);
+                }
+                if let Some((sep, tail)) = tail {
+                    out.push_str(sep);
+                    tail.flat(out);
+                }
+                out.push_str(close);
+            }
+        }
+    }
+
+    fn flat_string(&self) -> String {
+        let mut out = String::new();
+        self.flat(&mut out);
+        out
+    }
+}
+
+/// Something that can be pretty printed, terms and answers of named queries
+pub trait Layout: ToDoc {}
+
+impl<T: ToDoc> Layout for T {}
+
+/// Pretty printer, e.g. `Pretty { width: 40, ..Pretty::prolog() }.render(&term)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pretty {
+    pub syntax: Syntax,
+    /// Line width to fit groups in, counted in characters
+    pub width: usize,
+    /// Indentation of each nested group that is broken over lines
+    pub indent: usize,
+}
+
+impl Default for Pretty {
+    fn default() -> Self {
+        Self::scheme()
+    }
+}
+
+impl Pretty {
+    pub fn scheme() -> Self {
+        Self {
+            syntax: Syntax::Scheme,
+            width: 80,
+            indent: 2,
+        }
+    }
+
+    pub fn prolog() -> Self {
+        Self {
+            syntax: Syntax::Prolog,
+            ..Self::scheme()
+        }
+    }
+
+    pub fn render(&self, x: &impl Layout) -> String {
+        let doc = x.layout(self.syntax);
+        let mut out = String::new();
+        self.write(&Measured::new(&doc), 0, 0, &mut out);
+        out
+    }
+
+    // Write `doc` at `column` of the last line of `out`, continuation lines indented by `indent`.
+    // Returns the column after it.
+    fn write(&self, doc: &Measured, indent: usize, column: usize, out: &mut String) -> usize {
+        if column + doc.width <= self.width {
+            doc.doc.flat(out);
+            return column + doc.width;
+        }
+
+        match doc.doc {
+            Doc::Text(s) => {
+                out.push_str(s);
+                column + doc.width
+            }
+            Doc::Concat(_) => doc
+                .parts
+                .iter()
+                .fold(column, |column, d| self.write(d, indent, column, out)),
+            Doc::Group {
+                open,
+                sep,
+                tail,
+                close,
+                style,
+                ..
+            } => {
+                let inner = indent + self.indent;
+                let newline = |out: &mut String, indent: usize| {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                    indent
+                };
+                let push = |out: &mut String, s: &str, column: usize| {
+                    out.push_str(s);
+                    column + s.chars().count()
+                };
+                let mut column = push(out, open, column);
+                for (i, item) in doc.parts.iter().enumerate() {
+                    match style {
+                        Break::Hug if i == 0 => {}
+                        Break::Hug => column = newline(out, inner),
+                        Break::Block => {
+                            if i != 0 {
+                                push(out, sep.trim_end(), column);
+                            }
+                            column = newline(out, inner);
+                        }
+                        Break::Lines => {
+                            if i != 0 {
+                                push(out, sep.trim_end(), column);
+                                column = newline(out, indent);
+                            }
+                        }
+                    }
+                    let indent = if *style == Break::Lines {
+                        indent
+                    } else {
+                        inner
+                    };
+                    column = self.write(item, indent, column, out);
+                }
+                if let (Some((sep, _)), Some(tail)) = (tail, &doc.tail) {
+                    column = newline(out, inner);
+                    column = push(out, sep.trim_start(), column);
+                    column = self.write(tail, inner, column, out);
+                }
+                if *style == Break::Block {
+                    column = newline(out, indent);
+                }
+                push(out, close, column)
+            }
+        }
+    }
+}
+
+/// `Doc` with the width of each node written on one line, measured once before the layout
+struct Measured<'a> {
+    doc: &'a Doc,
+    width: usize,
+    /// Concatenated docs or items of a group
+    parts: Vec<Measured<'a>>,
+    tail: Option<Box<Measured<'a>>>,
+}
+
+impl<'a> Measured<'a> {
+    fn new(doc: &'a Doc) -> Self {
+        let len = |s: &str| s.chars().count();
+        let measure = |docs: &'a [Doc]| docs.iter().map(Measured::new).collect::<Vec<_>>();
+        let sum = |parts: &[Measured]| parts.iter().map(|p| p.width).sum::<usize>();
+        let (width, parts, tail) = match doc {
+            Doc::Text(s) => (len(s), Vec::new(), None),
+            Doc::Concat(docs) => {
+                let parts = measure(docs);
+                (sum(&parts), parts, None)
+            }
+            Doc::Group {
+                open,
+                items,
+                sep,
+                tail,
+                close,
+                ..
+            } => {
+                let parts = measure(items);
+                let seps = len(sep) * items.len().saturating_sub(1);
+                let mut width = len(open) + sum(&parts) + seps + len(close);
+                let tail = tail.as_ref().map(|(sep, tail)| {
+                    let tail = Measured::new(tail);
+                    width += len(sep) + tail.width;
+                    Box::new(tail)
+                });
+                (width, parts, tail)
+            }
+        };
+        Self {
+            doc,
+            width,
+            parts,
+            tail,
+        }
+    }
+}
+
+/// One line in Prolog syntax
+pub struct AsProlog<T: Layout>(pub T);
+
+impl<T: Layout> Display for AsProlog<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0.layout(Syntax::Prolog).flat_string())
+    }
+}
+
+/// Names of variables in Prolog syntax
+trait VarNames {
+    fn name(&self, var: Var) -> String;
+}
+
+/// Variables of a query, `_3`
+struct Plain;
+
+impl VarNames for Plain {
+    fn name(&self, var: Var) -> String {
+        format!("_{}", var.0)
+    }
+}
+
+/// Variables of an answer, numbered from 0 in order of appearance, named `_A`, `_B`, ... or after
+/// the query variable they are bound to
+struct Answer<'a>(&'a [(Var, String)]);
+
+impl VarNames for Answer<'_> {
+    fn name(&self, var: Var) -> String {
+        if let Some((_, name)) = self.0.iter().find(|(v, _)| *v == var) {
+            return name.clone();
+        }
+        let letter = char::from(b'A' + (var.0 % 26) as u8);
+        match var.0 / 26 {
+            0 => format!("_{letter}"),
+            n => format!("_{letter}{n}"),
+        }
+    }
+}
+
+fn symbol(t: TermType) -> &'static str {
+    match t {
+        TermType::Any => "_",
+        TermType::Number => "#",
+        TermType::String => "*",
+    }
+}
+
+/// Atom in Prolog syntax, quoted unless it is a plain lower case name
+fn atom(s: &str) -> String {
+    let mut chars = s.chars();
+    let plain = chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        return s.to_string();
+    }
+
+    let mut quoted = "'".to_string();
+    for c in s.chars() {
+        match c {
+            '\'' => quoted.push_str("\\'"),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Variables in `term` restricted to a type, each once in order of appearance
+fn typed_vars(term: &Term, vars: &mut Vec<(Var, TermType)>) {
+    match term {
+        Term::Var(v, t) if *t != TermType::Any && !vars.iter().any(|(x, _)| x == v) => {
+            vars.push((*v, *t))
+        }
+        Term::Cons(head, tail) => {
+            typed_vars(head, vars);
+            typed_vars(tail, vars);
+        }
+        Term::Set(elements, rest) => {
+            elements.iter().for_each(|x| typed_vars(x, vars));
+            typed_vars(rest, vars);
+        }
+        Term::Map(entries) => entries.iter().for_each(|(key, value)| {
+            typed_vars(key, vars);
+            typed_vars(value, vars);
+        }),
+        _ => {}
+    }
+}
+
+/// Prolog variable for the query variable `name`
+fn var_name(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().chain(chars).collect(),
+        _ => format!("_{name}"),
+    }
+}
+
+fn scheme(term: &Term, reified: bool) -> Doc {
+    let list = |items, tail| Doc::Group {
+        open: "(",
+        items,
+        sep: " ",
+        tail,
+        close: ")",
+        style: Break::Hug,
+    };
+
+    match term {
+        Term::Var(x, t) if reified => Doc::text(format!("{}.{}", symbol(*t), x.0)),
+        Term::Var(x, t) => Doc::text(format!("{}{}", symbol(*t), x.0)),
+        Term::Value(x) => Doc::text(x.to_string()),
+        Term::Type(t) => Doc::text(symbol(*t)),
+        Term::String(x) if x.contains(' ') => Doc::text(format!("\"{x}\"")),
+        Term::String(x) => Doc::text(x.clone()),
+        Term::Null => Doc::text("()"),
+        Term::Cons(_, _) => {
+            let mut items = Vec::new();
+            let mut rest = term;
+            while let Term::Cons(head, tail) = rest {
+                items.push(scheme(head, reified));
+                rest = tail;
+            }
+            let tail = (*rest != Term::Null).then(|| (" . ", Box::new(scheme(rest, reified))));
+            list(items, tail)
+        }
+        Term::Set(elements, rest) => Doc::Group {
+            open: "{",
+            items: elements.iter().map(|x| scheme(x, reified)).collect(),
+            sep: " ",
+            tail: (**rest != Term::Null).then(|| (" | ", Box::new(scheme(rest, reified)))),
+            close: "}",
+            style: Break::Hug,
+        },
+        Term::Map(entries) => {
+            let entries = entries.iter().map(|(key, value)| {
+                let value = Some((" . ", Box::new(scheme(value, reified))));
+                list(vec![scheme(key, reified)], value)
+            });
+            Doc::group("#hash(", entries.collect(), " ", ")", Break::Hug)
+        }
+    }
+}
+
+fn prolog(term: &Term, names: &dyn VarNames) -> Doc {
+    match term {
+        Term::Var(x, _) => Doc::text(names.name(*x)),
+        Term::Value(x) => Doc::text(x.to_string()),
+        Term::Type(TermType::Any) => Doc::text("_"),
+        Term::Type(t) => Doc::text(atom(symbol(*t))),
+        Term::String(x) => Doc::text(atom(x)),
+        Term::Null => Doc::text("[]"),
+        Term::Cons(_, _) => {
+            let mut items = Vec::new();
+            let mut rest = term;
+            while let Term::Cons(head, tail) = rest {
+                items.push(prolog(head, names));
+                rest = tail;
+            }
+            Doc::Group {
+                open: "[",
+                items,
+                sep: ",",
+                tail: (*rest != Term::Null).then(|| ("|", Box::new(prolog(rest, names)))),
+                close: "]",
+                style: Break::Block,
+            }
+        }
+        Term::Set(elements, rest) => Doc::Group {
+            open: "{",
+            items: elements.iter().map(|x| prolog(x, names)).collect(),
+            sep: ",",
+            tail: (**rest != Term::Null).then(|| ("|", Box::new(prolog(rest, names)))),
+            close: "}",
+            style: Break::Block,
+        },
+        Term::Map(entries) => {
+            let entries = entries.iter().map(|(key, value)| {
+                Doc::Concat(vec![
+                    prolog(key, names),
+                    Doc::text(":"),
+                    prolog(value, names),
+                ])
+            });
+            Doc::group("_{", entries.collect(), ",", "}", Break::Block)
+        }
+    }
+}
+
+impl ToDoc for Term {
+    fn layout(&self, syntax: Syntax) -> Doc {
+        match syntax {
+            Syntax::Scheme => scheme(self, false),
+            Syntax::Prolog => prolog(self, &Plain),
+        }
+    }
+}
+
+impl<T: ToDoc> ToDoc for &T {
+    fn layout(&self, syntax: Syntax) -> Doc {
+        (*self).layout(syntax)
+    }
+}
+
+impl ToDoc for Bindings {
+    fn layout(&self, syntax: Syntax) -> Doc {
+        match syntax {
+            Syntax::Scheme => {
+                let bindings = self.iter().map(|(name, term)| {
+                    Doc::Concat(vec![Doc::text(format!("{name} = ")), scheme(term, true)])
+                });
+                let bindings = Doc::group("", bindings.collect(), ", ", "", Break::Lines);
+                if self.constraints().is_empty() && self.delayed().is_empty() {
+                    return bindings;
+                }
+
+                let constraints = self.constraints().iter().map(|constraint| {
+                    let pairs = constraint.iter().map(|(v, t)| {
+                        let v = scheme(&Term::from(*v), true);
+                        Doc::Group {
+                            open: "(",
+                            items: vec![v],
+                            sep: " ",
+                            tail: Some((" . ", Box::new(scheme(t, true)))),
+                            close: ")",
+                            style: Break::Hug,
+                        }
+                    });
+                    Doc::group("(", pairs.collect(), " ", ")", Break::Hug)
+                });
+                let delayed = self.delayed().iter().map(|form| {
+                    let name = Doc::text(form.name.to_string());
+                    let args = form.args.iter().map(|t| scheme(t, true));
+                    Doc::group(
+                        "(",
+                        [name].into_iter().chain(args).collect(),
+                        " ",
+                        ")",
+                        Break::Hug,
+                    )
+                });
+                let constraints = constraints.chain(delayed).collect();
+                let constraints = Doc::group("(", constraints, " ", ")", Break::Hug);
+                Doc::Concat(vec![bindings, Doc::text(" : "), constraints])
+            }
+            Syntax::Prolog => {
+                // A query variable left unbound is named after the first query variable that has it
+                let mut vars: Vec<(Var, String)> = Vec::new();
+                let mut items = Vec::new();
+                for (name, term) in self.iter() {
+                    match term {
+                        Term::Var(v, _) if !vars.iter().any(|(x, _)| x == v) => {
+                            vars.push((*v, var_name(name)));
+                        }
+                        _ => items.push((name, term)),
+                    }
+                }
+                let names = Answer(&vars);
+
+                let mut items: Vec<_> = items
+                    .into_iter()
+                    .map(|(name, term)| {
+                        let name = Doc::text(format!("{} = ", var_name(name)));
+                        Doc::Concat(vec![name, prolog(term, &names)])
+                    })
+                    .collect();
+                // Variables restricted to a type, as type checks before the `dif/2` constraints
+                let mut typed = Vec::new();
+                let delayed = self.delayed().iter().flat_map(|form| &form.args);
+                let constraints = self.constraints().iter().flatten().map(|(_, t)| t);
+                let bindings = self.iter().map(|(_, t)| t);
+                for term in bindings.chain(constraints).chain(delayed) {
+                    typed_vars(term, &mut typed);
+                }
+                for (v, t) in typed {
+                    let check = match t {
+                        TermType::Number => "integer(",
+                        _ => "atom(",
+                    };
+                    let var = prolog(&Term::from(v), &names);
+                    items.push(Doc::group(check, vec![var], ", ", ")", Break::Hug));
+                }
+                for constraint in self.constraints() {
+                    let (vars, terms): (Vec<_>, Vec<_>) = constraint
+                        .iter()
+                        .map(|(v, t)| (prolog(&Term::from(*v), &names), prolog(t, &names)))
+                        .unzip();
+                    // One of the pairs differs, the same as the lists of them differing
+                    let args = match (vars.len(), vars.first(), terms.first()) {
+                        (1, Some(v), Some(t)) => vec![v.clone(), t.clone()],
+                        _ => vec![
+                            Doc::group("[", vars, ",", "]", Break::Block),
+                            Doc::group("[", terms, ",", "]", Break::Block),
+                        ],
+                    };
+                    items.push(Doc::group("dif(", args, ", ", ")", Break::Hug));
+                }
+                for form in self.delayed() {
+                    let args = form.args.iter().map(|t| prolog(t, &names)).collect();
+                    let args = Doc::group("(", args, ", ", ")", Break::Hug);
+                    items.push(Doc::Concat(vec![Doc::text(form.name.to_string()), args]));
+                }
+
+                if items.is_empty() {
+                    items.push(Doc::text("true"));
+                }
+                Doc::group("", items, ", ", "", Break::Lines)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+    use crate::*;
+
+    #[test]
+    fn scheme_flat() {
+        let terms = [
+            list!(1, "a b", list!(), list!(Var(2), . Var(3))),
+            cons(1, 2),
+            Term::set_with([&Term::from(1), &Term::from(Var(0))], Var(1)),
+            Term::set_with([&Term::from("x")], NULL),
+            list!(NUM, STR, ANY),
+        ];
+        for term in terms {
+            let pretty = Pretty {
+                width: usize::MAX,
+                ..Pretty::scheme()
+            };
+            assert_eq!(pretty.render(&term), AsScheme(&term).to_string());
+        }
+    }
+
+    #[test]
+    fn prolog_terms() {
+        let term = list!(1, 2, . Var(3));
+        assert_eq!(AsProlog(&term).to_string(), "[1,2|_3]");
+        assert_eq!(
+            AsProlog(list!("a", "quoted atom", "It's", "B")).to_string(),
+            "[a,'quoted atom','It\\'s','B']"
+        );
+        assert_eq!(AsProlog(list!(list!(), NULL, ANY)).to_string(), "[[],[],_]");
+        let set = Term::set_with([&Term::from(1)], Var(0));
+        assert_eq!(AsProlog(set).to_string(), "{1|_0}");
+    }
+
+    #[test]
+    fn prolog_answers() {
+        let answers: Vec<_> = query_named(["x", "y"], |x, y| all([num(x), str(y)])).collect();
+        assert_eq!(AsProlog(&answers[0]).to_string(), "integer(X), atom(Y)");
+
+        let answers: Vec<_> = query_named(["x", "y", "z"], |x, y, z| {
+            all([
+                neq(x, 5),
+                eq(y, list!(1, x, z)),
+                neq(list!(x, z), list!(1, 2)),
+            ])
+        })
+        .collect();
+        assert_eq!(
+            AsProlog(&answers[0]).to_string(),
+            "Y = [1,X,Z], dif([X,Z], [1,2]), dif(X, 5)"
+        );
+
+        let answers: Vec<_> = query_named(["x"], |x| eq(x, x)).collect();
+        assert_eq!(AsProlog(&answers[0]).to_string(), "true");
+
+        let answers: Vec<_> = query_named(["q", "r"], |q, r| {
+            fresh(move |a| all([eq(q, list!(a)), eq(r, a)]))
+        })
+        .collect();
+        assert_eq!(AsProlog(&answers[0]).to_string(), "Q = [R]");
+        assert_eq!(Pretty::scheme().render(&answers[0]), answers[0].to_string());
+
+        let answers: Vec<_> =
+            query_named(["x", "y"], |x, y| all([neq(x, 1), relset::term_lt(x, y)])).collect();
+        assert_eq!(
+            AsProlog(&answers[0]).to_string(),
+            "dif(X, 1), term_lt(X, Y)"
+        );
+        assert_eq!(
+            answers[0].to_string(),
+            "x = _.0, y = _.1 : (((_.0 . 1)) (term_lt _.0 _.1))"
+        );
+        assert_eq!(Pretty::scheme().render(&answers[0]), answers[0].to_string());
+
+        let answers: Vec<_> = query_named(["x", "y"], |x, y| all([num(x), str(y)])).collect();
+        assert_eq!(AsProlog(&answers[0]).to_string(), "integer(X), atom(Y)");
+
+        let answers: Vec<_> = query_named(["x", "y", "z"], |x, y, z| {
+            all([num(x), str(y), eq(z, list!(x, y))])
+        })
+        .collect();
+        assert_eq!(
+            AsProlog(&answers[0]).to_string(),
+            "Z = [X,Y], integer(X), atom(Y)"
+        );
+    }
+
+    #[test]
+    fn breaks() {
+        let term = list!(list!(1, 2, 3), list!("a", "b"), . Var(4));
+        assert_eq!(
+            Pretty {
+                width: 12,
+                ..Pretty::scheme()
+            }
+            .render(&term),
+            "((1 2 3)\n  (a b)\n  . _4)"
+        );
+        assert_eq!(
+            Pretty {
+                width: 12,
+                indent: 4,
+                ..Pretty::prolog()
+            }
+            .render(&term),
+            "[\n    [1,2,3],\n    [a,b]\n    |_4\n]"
+        );
+        assert_eq!(
+            Pretty {
+                width: 5,
+                ..Pretty::prolog()
+            }
+            .render(&list!(list!(1, 2))),
+            "[\n  [\n    1,\n    2\n  ]\n]"
+        );
+        assert_eq!(Pretty::prolog().render(&term), "[[1,2,3],[a,b]|_4]");
+
+        // Columns count characters, not bytes
+        let answers: Vec<_> = query_named(["ü"], |x| eq(x, list!(list!(1, 2), 3))).collect();
+        assert_eq!(
+            Pretty {
+                width: 10,
+                ..Pretty::scheme()
+            }
+            .render(&answers[0]),
+            "ü = ((1 2)\n  3)"
+        );
+
+        // Every line of a long list is laid out without looking back at the ones before
+        let term = (0..2000).fold(NULL, |list, _| cons(list!(1, 2), list));
+        let lines = vec!["(1 2)"; 2000].join("\n  ");
+        assert_eq!(
+            Pretty {
+                width: 10,
+                ..Pretty::scheme()
+            }
+            .render(&term),
+            format!("({lines})")
+        );
+    }
+}
@@ -0,0 +1,304 @@
+// Relational sets
+//
+// A set is a list in canonical order: strictly ascending by the `Ord` of `Term`, which also
+// means without duplicates. Relations produce canonical sets, so every set has exactly one
+// representation and answers aren't repeated for each permutation. `set_eq`, `subset` and
+// `superset` compare any lists as sets, ground arguments are normalized with `set_of`. The other
+// relations expect their set arguments to be canonical, use `set_of` for an arbitrary list.
+// Arguments that aren't ground yet are taken as canonical.
+//
+// Ordering needs ground elements, comparisons of unbound elements are delayed with `project`
+// until both sides are ground, and stay in the answer as `term_lt` goals if they never are.
+//
+// Termination: a relation has finitely many answers when the sets it reads are bounded, i.e.
+// proper lists. These are `set` for `contains`, `excludes`, `set_minimal`, `set_insert` and
+// `set_remove`, `b` for `subset`, `a` for `superset`, either side of `set_eq`, both sides of
+// `set_join` and `list` for `set_of`.
+
+use crate::goal;
+use crate::*;
+
+/// `a` is ordered before `b`, delayed until both are ground. A term is never before itself,
+/// so the same term on both sides fails right away.
+pub fn term_lt(a: impl Into<Term>, b: impl Into<Term>) -> Goal {
+    let terms = [a.into(), b.into()];
+    project_named("term_lt", terms.clone(), terms, |terms| {
+        let [a, b] = terms else { unreachable!() };
+        if a == b {
+            return Some(fail());
+        }
+        (a.is_ground() && b.is_ground()).then(|| if a < b { succeed() } else { fail() })
+    })
+}
+
+// `set` is a proper list
+goal!(
+    pub fn bounded(set: Var) -> Goal {
+        fresh(move |head, tail| {
+            cond([
+                vec![eq(set, NULL)],
+                vec![eq(set, cons(head, tail)), jield(move || bounded(tail))],
+            ])
+        })
+    }
+);
+
+// `x` is an element of the canonical `set`
+goal!(
+    pub fn contains(set: Var, x: Var) -> Goal {
+        fresh(move |head, tail| {
+            cond([
+                vec![eq(set, cons(head, tail)), eq(head, x), bounded(tail)],
+                vec![
+                    eq(set, cons(head, tail)),
+                    neq(head, x),
+                    jield(move || contains(tail, x)),
+                ],
+            ])
+        })
+    }
+);
+
+// `x` isn't an element of the canonical `set`
+goal!(
+    pub fn excludes(set: Var, x: Var) -> Goal {
+        fresh(move |head, tail| {
+            cond([
+                vec![eq(set, NULL)],
+                vec![
+                    eq(set, cons(head, tail)),
+                    neq(head, x),
+                    jield(move || excludes(tail, x)),
+                ],
+            ])
+        })
+    }
+);
+
+// `set` is canonical, strictly ascending and so without duplicates
+goal!(
+    pub fn set_minimal(set: Var) -> Goal {
+        fresh(move |a, b, tail| {
+            cond([
+                vec![eq(set, NULL)],
+                vec![eq(set, list!(a))],
+                vec![
+                    eq(set, cons(a, cons(b, tail))),
+                    term_lt(a, b),
+                    jield(move || set_minimal(cons(b, tail))),
+                ],
+            ])
+        })
+    }
+);
+
+// `set` is the canonical set of `list` if that is ground, otherwise `list` taken as canonical
+fn canonical(list: Var, set: Var) -> Goal {
+    project([list.into()], move |terms| {
+        Some(if terms[0].is_ground() {
+            set_of(list, set)
+        } else {
+            eq(list, set)
+        })
+    })
+}
+
+// Lists `a` and `b` have the same elements
+goal!(
+    pub fn set_eq(a: Var, b: Var) -> Goal {
+        fresh(move |set| all([canonical(a, set), canonical(b, set), set_minimal(set)]))
+    }
+);
+
+// The elements of list `a` are elements of `b`
+goal!(
+    pub fn subset(a: Var, b: Var) -> Goal {
+        fresh(move |set_a, set_b| {
+            all([
+                canonical(b, set_b),
+                canonical(a, set_a),
+                sublist(set_a, set_b),
+                set_minimal(set_b),
+            ])
+        })
+    }
+);
+
+// `a` is `b` with some elements left out, keeping the order
+goal!(
+    fn sublist(a: Var, b: Var) -> Goal {
+        fresh(move |head, tail, rest| {
+            cond([
+                vec![eq(b, NULL), eq(a, NULL)],
+                vec![
+                    eq(b, cons(head, tail)),
+                    eq(a, cons(head, rest)),
+                    jield(move || sublist(rest, tail)),
+                ],
+                vec![eq(b, cons(head, tail)), jield(move || sublist(a, tail))],
+            ])
+        })
+    }
+);
+
+goal!(
+    pub fn superset(a: Var, b: Var) -> Goal {
+        subset(b, a)
+    }
+);
+
+// `result` is the canonical `set` with `x` added
+goal!(
+    pub fn set_insert(set: Var, x: Var, result: Var) -> Goal {
+        fresh(move |head, tail, c| {
+            cond([
+                vec![eq(set, NULL), eq(result, list!(x))],
+                vec![eq(set, cons(head, tail)), eq(head, x), eq(result, set)],
+                vec![
+                    eq(set, cons(head, tail)),
+                    term_lt(x, head),
+                    eq(result, cons(x, set)),
+                ],
+                vec![
+                    eq(set, cons(head, tail)),
+                    term_lt(head, x),
+                    eq(result, cons(head, c)),
+                    jield(move || set_insert(tail, x, c)),
+                ],
+            ])
+        })
+    }
+);
+
+// `result` is the canonical `set` without `x`
+goal!(
+    pub fn set_remove(set: Var, x: Var, result: Var) -> Goal {
+        fresh(move |head, tail, c| {
+            cond([
+                vec![eq(set, NULL), eq(result, NULL)],
+                vec![eq(set, cons(head, tail)), eq(head, x), eq(result, tail)],
+                vec![
+                    eq(set, cons(head, tail)),
+                    neq(head, x),
+                    eq(result, cons(head, c)),
+                    jield(move || set_remove(tail, x, c)),
+                ],
+            ])
+        })
+    }
+);
+
+// `result` is the union of the canonical sets `a` and `b`
+goal!(
+    pub fn set_join(a: Var, b: Var, result: Var) -> Goal {
+        fresh(move |a_head, a_tail, b_head, b_tail, c| {
+            cond([
+                vec![eq(a, NULL), eq(result, b)],
+                vec![eq(a, cons(a_head, a_tail)), eq(b, NULL), eq(result, a)],
+                vec![
+                    eq(a, cons(a_head, a_tail)),
+                    eq(b, cons(b_head, b_tail)),
+                    cond([
+                        vec![
+                            eq(a_head, b_head),
+                            eq(result, cons(a_head, c)),
+                            jield(move || set_join(a_tail, b_tail, c)),
+                        ],
+                        vec![
+                            term_lt(a_head, b_head),
+                            eq(result, cons(a_head, c)),
+                            jield(move || set_join(a_tail, b, c)),
+                        ],
+                        vec![
+                            term_lt(b_head, a_head),
+                            eq(result, cons(b_head, c)),
+                            jield(move || set_join(a, b_tail, c)),
+                        ],
+                    ]),
+                ],
+            ])
+        })
+    }
+);
+
+// `set` is the canonical set of the elements of `list`
+goal!(
+    pub fn set_of(list: Var, set: Var) -> Goal {
+        fresh(move |head, tail, s| {
+            cond([
+                vec![eq(list, NULL), eq(set, NULL)],
+                vec![
+                    eq(list, cons(head, tail)),
+                    jield(move || set_of(tail, s)),
+                    set_insert(s, head, set),
+                ],
+            ])
+        })
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    #[test]
+    fn canonical() {
+        let result = run_all(|s| set_of(list!(3, 1, 2, 1), s));
+        assert_eq!(AsScheme(result).to_string(), "(((1 2 3)))");
+
+        let result = run_all(|s| all([subset(s, list!(1, 2, 3)), eq(s, list!(2, 1))]));
+        assert_eq!(AsScheme(result).to_string(), "()");
+
+        let result = run_all(|s| subset(s, list!(1, 2)));
+        assert_eq!(AsScheme(result).to_string(), "(((1 2)) ((1)) ((2)) (()))");
+    }
+
+    #[test]
+    fn unordered() {
+        // Lists in any order and with duplicates compare as sets
+        assert_eq!(run_all(|_| set_eq(list!(2, 1), list!(1, 2))).len(), 1);
+        assert_eq!(run_all(|_| set_eq(list!(1, 2, 1), list!(2, 1, 2))).len(), 1);
+        assert!(run_all(|_| set_eq(list!(2, 1), list!(1, 3))).is_empty());
+
+        let result = run_all(|s| set_eq(s, list!(3, 1, 3)));
+        assert_eq!(AsScheme(result).to_string(), "(((1 3)))");
+
+        let result = run_all(|s| subset(s, list!(2, 1, 2)));
+        assert_eq!(AsScheme(result).to_string(), "(((1 2)) ((1)) ((2)) (()))");
+        assert_eq!(run_all(|_| subset(list!(3, 1, 1), list!(3, 2, 1))).len(), 1);
+        assert!(run_all(|_| subset(list!(3, 4), list!(3, 2, 1))).is_empty());
+        assert_eq!(run_all(|_| superset(list!(2, 1), list!(1, 1))).len(), 1);
+    }
+
+    #[test]
+    fn delayed_order() {
+        // Comparisons wait for the elements to be bound
+        let result = run_all(|x, s| all([set_insert(list!(1, 3), x, s), eq(x, 2)]));
+        assert_eq!(AsScheme(result).to_string(), "((2 (1 2 3)))");
+
+        let result = run_all(|x| all([set_minimal(list!(1, x, 3)), any([eq(x, 0), eq(x, 2)])]));
+        assert_eq!(AsScheme(result).to_string(), "((2))");
+
+        // An element is never before itself
+        assert!(run_all(|x| set_minimal(list!(x, x))).is_empty());
+        assert!(run_all(|x, y| all([set_minimal(list!(x, y)), eq(x, y)])).is_empty());
+
+        // Comparisons never decided stay in the answer
+        let result = run_all(|x, y| set_minimal(list!(x, y)));
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "((_0 _1) : ((term_lt _0 _1)))"
+        );
+    }
+
+    #[test]
+    fn join() {
+        let result = run_all(|q| set_join(list!(1, 3, 5), list!(2, 3, 4), q));
+        assert_eq!(AsScheme(result).to_string(), "(((1 2 3 4 5)))");
+
+        let result =
+            run_all(|a, b| all([set_join(a, b, list!(1, 2)), set_minimal(a), set_minimal(b)]));
+        assert_eq!(result.len(), 9);
+    }
+}
@@ -0,0 +1,354 @@
+// Serde support, enabled with the `serde` feature.
+//
+// Terms use a stable JSON friendly encoding:
+// - numbers and strings as themselves
+// - proper lists as arrays, `()` as `[]`
+// - improper lists as `{"list": [1, 2], "tail": {"var": 0}}`
+// - variables as `{"var": 0}`, typed variables as `{"var": 0, "type": "number"}`
+// - type terms as `{"type": "number"}`
+// - sets as `{"set": [1, 2]}`, open sets as `{"set": [1], "rest": {"var": 0}}`
+// - records as `{"map": [["a", 1], ["b", 2]]}`
+
+use crate::{cons, Term, TermType, Var, NULL};
+
+use serde::de::{Error, MapAccess, SeqAccess, Unexpected, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+impl Serialize for TermType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            TermType::Any => "any",
+            TermType::Number => "number",
+            TermType::String => "string",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for TermType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "any" => Ok(TermType::Any),
+            "number" => Ok(TermType::Number),
+            "string" => Ok(TermType::String),
+            other => Err(D::Error::unknown_variant(other, &["any", "number", "string"])),
+        }
+    }
+}
+
+impl Serialize for Var {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry("var", &self.0)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Var {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Repr {
+            var: u32,
+        }
+
+        Repr::deserialize(deserializer).map(|r| Var(r.var))
+    }
+}
+
+/// List elements and the tail, `()` for a proper list
+fn elements(term: &Term) -> (Vec<&Term>, &Term) {
+    let mut items = Vec::new();
+    let mut rest = term;
+    while let Term::Cons(head, tail) = rest {
+        items.push(head.as_ref());
+        rest = tail;
+    }
+    (items, rest)
+}
+
+impl Serialize for Term {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Term::Value(i) => serializer.serialize_i32(*i),
+            Term::String(s) => serializer.serialize_str(s),
+            Term::Var(v, TermType::Any) => v.serialize(serializer),
+            Term::Var(v, t) => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("var", &v.0)?;
+                map.serialize_entry("type", t)?;
+                map.end()
+            }
+            Term::Type(t) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("type", t)?;
+                map.end()
+            }
+            Term::Set(elements, rest) => {
+                let open = **rest != NULL;
+                let mut map = serializer.serialize_map(Some(1 + open as usize))?;
+                map.serialize_entry("set", elements.as_ref())?;
+                if open {
+                    map.serialize_entry("rest", rest.as_ref())?;
+                }
+                map.end()
+            }
+            Term::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("map", entries.as_ref())?;
+                map.end()
+            }
+            Term::Null | Term::Cons(_, _) => {
+                let (items, tail) = elements(self);
+                if *tail == NULL {
+                    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                    for item in items {
+                        seq.serialize_element(item)?;
+                    }
+                    seq.end()
+                } else {
+                    let mut map = serializer.serialize_map(Some(2))?;
+                    map.serialize_entry("list", &items)?;
+                    map.serialize_entry("tail", tail)?;
+                    map.end()
+                }
+            }
+        }
+    }
+}
+
+fn list(items: Vec<Term>, tail: Term) -> Term {
+    items.into_iter().rev().fold(tail, |tail, item| cons(item, tail))
+}
+
+/// Fields of an object term, the first of `var`, `list`, `set` and `map` says what it is
+#[derive(Default)]
+struct Fields {
+    var: Option<u32>,
+    r#type: Option<TermType>,
+    list: Option<Vec<Term>>,
+    tail: Option<Term>,
+    set: Option<Vec<Term>>,
+    rest: Option<Term>,
+    map: Option<Vec<(Term, Term)>>,
+}
+
+const FIELDS: &[&str] = &["var", "type", "list", "tail", "set", "rest", "map"];
+
+impl Fields {
+    fn read<'de, A: MapAccess<'de>>(mut access: A) -> Result<Self, A::Error> {
+        fn once<'de, A: MapAccess<'de>, T: Deserialize<'de>>(
+            access: &mut A,
+            field: &mut Option<T>,
+            name: &'static str,
+        ) -> Result<(), A::Error> {
+            if field.is_some() {
+                return Err(A::Error::duplicate_field(name));
+            }
+            *field = Some(access.next_value()?);
+            Ok(())
+        }
+
+        let mut fields = Fields::default();
+        while let Some(key) = access.next_key::<String>()? {
+            match key.as_str() {
+                "var" => once(&mut access, &mut fields.var, "var")?,
+                "type" => once(&mut access, &mut fields.r#type, "type")?,
+                "list" => once(&mut access, &mut fields.list, "list")?,
+                "tail" => once(&mut access, &mut fields.tail, "tail")?,
+                "set" => once(&mut access, &mut fields.set, "set")?,
+                "rest" => once(&mut access, &mut fields.rest, "rest")?,
+                "map" => once(&mut access, &mut fields.map, "map")?,
+                other => return Err(A::Error::unknown_field(other, FIELDS)),
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Term of the fields, only the fields of its kind are allowed
+    fn term<E: Error>(self) -> Result<Term, E> {
+        let present = [
+            ("var", self.var.is_some()),
+            ("type", self.r#type.is_some()),
+            ("list", self.list.is_some()),
+            ("tail", self.tail.is_some()),
+            ("set", self.set.is_some()),
+            ("rest", self.rest.is_some()),
+            ("map", self.map.is_some()),
+        ];
+        let only = |allowed: &'static [&'static str]| match present
+            .iter()
+            .find(|(name, present)| *present && !allowed.contains(name))
+        {
+            Some((name, _)) => Err(E::unknown_field(name, allowed)),
+            None => Ok(()),
+        };
+
+        if let Some(var) = self.var {
+            only(&["var", "type"])?;
+            Ok(Term::Var(Var(var), self.r#type.unwrap_or(TermType::Any)))
+        } else if let Some(items) = self.list {
+            only(&["list", "tail"])?;
+            let tail = self.tail.ok_or_else(|| E::missing_field("tail"))?;
+            Ok(list(items, tail))
+        } else if let Some(set) = self.set {
+            only(&["set", "rest"])?;
+            Ok(Term::set_with(set, self.rest.unwrap_or(NULL)))
+        } else if let Some(map) = self.map {
+            only(&["map"])?;
+            if let Some((key, _)) = map.iter().find(|(key, _)| !key.is_ground()) {
+                return Err(E::custom(format!("record key {key:?} isn't ground")));
+            }
+            Ok(Term::map(map))
+        } else if let Some(t) = self.r#type {
+            only(&["type"])?;
+            Ok(Term::Type(t))
+        } else {
+            Err(E::custom("expected one of the fields var, list, set, map or type"))
+        }
+    }
+}
+
+struct TermVisitor;
+
+impl<'de> Visitor<'de> for TermVisitor {
+    type Value = Term;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a number, string, array or term object")
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<Term, E> {
+        let i = i32::try_from(v).map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))?;
+        Ok(Term::Value(i))
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Term, E> {
+        let i = i32::try_from(v).map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))?;
+        Ok(Term::Value(i))
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Term, E> {
+        Ok(Term::from(v))
+    }
+
+    fn visit_string<E: Error>(self, v: String) -> Result<Term, E> {
+        Ok(Term::String(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<Term, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = access.next_element()? {
+            items.push(item);
+        }
+        Ok(list(items, NULL))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, access: A) -> Result<Term, A::Error> {
+        Fields::read(access)?.term()
+    }
+}
+
+impl<'de> Deserialize<'de> for Term {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TermVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde_json::json;
+
+    fn roundtrip(term: Term, expected: serde_json::Value) {
+        let value = serde_json::to_value(&term).unwrap();
+        assert_eq!(value, expected);
+        assert_eq!(serde_json::from_value::<Term>(value).unwrap(), term);
+    }
+
+    #[test]
+    fn terms() {
+        roundtrip(Term::from(5), json!(5));
+        roundtrip(Term::from("hi"), json!("hi"));
+        roundtrip(NULL, json!([]));
+        roundtrip(list!(1, "a", list!()), json!([1, "a", []]));
+        roundtrip(
+            list!(1, 2, . Var(3)),
+            json!({"list": [1, 2], "tail": {"var": 3}}),
+        );
+        roundtrip(cons(1, 2), json!({"list": [1], "tail": 2}));
+        roundtrip(Term::from(Var(0)), json!({"var": 0}));
+        roundtrip(
+            Term::Var(Var(1), TermType::Number),
+            json!({"var": 1, "type": "number"}),
+        );
+        roundtrip(STR, json!({"type": "string"}));
+        roundtrip(Term::set([1, 2]), json!({"set": [1, 2]}));
+        roundtrip(
+            Term::set_with(["a"], Var(0)),
+            json!({"set": ["a"], "rest": {"var": 0}}),
+        );
+        roundtrip(
+            Term::map([("b", Term::from(2)), ("a", Term::from(Var(0)))]),
+            json!({"map": [["a", {"var": 0}], ["b", 2]]}),
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |value| serde_json::from_value::<Term>(value).unwrap_err().to_string();
+        assert_eq!(
+            error(json!({"var": 0, "foo": 1})),
+            "unknown field `foo`, expected one of `var`, `type`, `list`, `tail`, `set`, `rest`, `map`"
+        );
+        assert_eq!(
+            error(json!({"var": 0, "set": []})),
+            "unknown field `set`, expected `var` or `type`"
+        );
+        assert_eq!(error(json!({"list": [1]})), "missing field `tail`");
+        assert_eq!(
+            error(json!({"var": "x"})),
+            "invalid type: string \"x\", expected u32"
+        );
+        assert_eq!(
+            error(json!([1, {"type": "bool"}])),
+            "unknown variant `bool`, expected one of `any`, `number`, `string`"
+        );
+        assert_eq!(
+            error(json!(1.5)),
+            "invalid type: floating point `1.5`, expected a number, string, array or term object"
+        );
+        assert_eq!(
+            error(json!({"map": [[{"var": 0}, 1]]})),
+            "record key Var(Var(0), Any) isn't ground"
+        );
+    }
+
+    #[test]
+    fn answer() {
+        let result = run_all(|x, y| fresh(move |z| all([eq(x, list!(1, z)), neq(y, z)])));
+        let answer = result[0].answer();
+        let value = serde_json::to_value(&answer).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "terms": [[1, {"var": 0}], {"var": 1}],
+                "constraints": [[[{"var": 0}, {"var": 1}]]],
+            })
+        );
+        assert_eq!(serde_json::from_value::<Answer>(value).unwrap(), answer);
+
+        let result = run_all(crate::relset::term_lt);
+        let answer = result[0].answer();
+        let value = serde_json::to_value(&answer).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "terms": [{"var": 0}, {"var": 1}],
+                "constraints": [],
+                "delayed": [{"name": "term_lt", "args": [{"var": 0}, {"var": 1}]}],
+            })
+        );
+        assert_eq!(serde_json::from_value::<Answer>(value).unwrap(), answer);
+    }
+}
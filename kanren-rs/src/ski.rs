@@ -0,0 +1,336 @@
+// SKI combinator calculus
+//
+// Expressions are lists of combinators `S`, `K` and `I`, where a nested list is a parenthesized
+// sub expression, see `parse`. `eval` reduces an expression applied to fresh variables `a`, `b`,
+// `c`, ... until no combinator is left in head position, giving the λ-term it stands for. Both
+// the variables and the term are kept in reverse order, so `λab.ba` is the variables `(b a)` and
+// the term `(a b)`, see `lambda` and `fmt_lambda`.
+//
+// Running `eval` backwards with `combs` synthesizes combinator expressions for a λ-term.
+
+use crate::convert::FromTerm;
+use crate::goal;
+use crate::*;
+
+// `x` is the next fresh variable after the variables `v`, `v_` is `v` with `x` added
+goal!(
+    fn next_var(x: Var, v: Var, v_: Var) -> Goal {
+        fresh(move |dummy| {
+            cond([
+                vec![eq(v, NULL), eq(x, "a"), eq(v_, cons(x, v))],
+                vec![eq(v, cons("a", dummy)), eq(x, "b"), eq(v_, cons(x, v))],
+                vec![eq(v, cons("b", dummy)), eq(x, "c"), eq(v_, cons(x, v))],
+                vec![eq(v, cons("c", dummy)), eq(x, "d"), eq(v_, cons(x, v))],
+                vec![eq(v, cons("d", dummy)), eq(x, "e"), eq(v_, cons(x, v))],
+            ])
+        })
+    }
+);
+
+// Binds the argument of `comb`, taking a fresh variable if `e` has none
+goal!(
+    fn bind1(e: Var, comb: Var, a: Var, rem: Var, v: Var, v_: Var) -> Goal {
+        cond([
+            vec![eq(e, list!(comb, a, . rem)), eq(v, v_)],
+            vec![eq(e, list!(comb)), eq(rem, NULL), next_var(a, v, v_)],
+        ])
+    }
+);
+
+goal!(
+    fn bind2(e: Var, comb: Var, a: Var, b: Var, rem: Var, v: Var, v_: Var) -> Goal {
+        fresh(move |vn| {
+            cond([
+                vec![eq(e, list!(comb, a, b, . rem)), eq(v, v_)],
+                vec![eq(e, list!(comb, a)), eq(rem, NULL), next_var(b, v, v_)],
+                vec![
+                    eq(e, list!(comb)),
+                    eq(rem, NULL),
+                    next_var(a, v, vn),
+                    next_var(b, vn, v_),
+                ],
+            ])
+        })
+    }
+);
+
+goal!(
+    fn bind3(e: Var, comb: Var, a: Var, b: Var, c: Var, rem: Var, v: Var, v_: Var) -> Goal {
+        fresh(move |v0, v1| {
+            cond([
+                vec![eq(e, list!(comb, a, b, c, . rem)), eq(v, v_)],
+                vec![eq(e, list!(comb, a, b)), eq(rem, NULL), next_var(c, v, v_)],
+                vec![
+                    eq(e, list!(comb, a)),
+                    eq(rem, NULL),
+                    next_var(b, v, v0),
+                    next_var(c, v0, v_),
+                ],
+                vec![
+                    eq(e, list!(comb)),
+                    eq(rem, NULL),
+                    next_var(a, v, v0),
+                    next_var(b, v0, v1),
+                    next_var(c, v1, v_),
+                ],
+            ])
+        })
+    }
+);
+
+// One reduction step of the head combinator of `e`, `v` and `v_` are the variables before and
+// after the step
+goal!(
+    pub fn apply(e: Var, e_: Var, v: Var, v_: Var) -> Goal {
+        fresh(move |a, b, c, rem| {
+            cond([
+                vec![bind1(e, "I", a, rem, v, v_), eq(e_, cons(a, rem))],
+                vec![bind2(e, "K", a, b, rem, v, v_), eq(e_, cons(a, rem))],
+                vec![
+                    bind3(e, "S", a, b, c, rem, v, v_),
+                    eq(e_, list!(a, c, list!(b, c), . rem)),
+                ],
+            ])
+        })
+    }
+);
+
+// The head of `e` is one of the variables `v`, moved to the term `t`
+goal!(
+    fn var(e: Var, e_: Var, t: Var, t_: Var, v: Var) -> Goal {
+        fresh(move |x| all([eq(e, cons(x, e_)), eq(t_, cons(x, t)), list::contains(v, x)]))
+    }
+);
+
+goal!(
+    fn eval_inner(e: Var, v: Var, v_: Var, t: Var, t_: Var) -> Goal {
+        cond([
+            // Empty expression
+            vec![eq(e, NULL), eq(v, v_), eq(t, t_)],
+            // Apply combinator
+            vec![fresh(move |te, tv| {
+                all([
+                    apply(e, te, v, tv),
+                    jield(move || eval_inner(te, tv, v_, t, t_)),
+                ])
+            })],
+            // If var, no further reduction is possible, move head to term
+            vec![fresh(move |te, tt| {
+                all([
+                    var(e, te, t, tt, v),
+                    jield(move || eval_inner(te, v, v_, tt, t_)),
+                ])
+            })],
+            // Unwrap head if term is empty
+            vec![fresh(move |head, tail, te| {
+                all([
+                    eq(t, NULL),
+                    eq(e, cons(head, tail)),
+                    list::at_least_two(head),
+                    list::append(head, tail, te),
+                    jield(move || eval_inner(te, v, v_, t, t_)),
+                ])
+            })],
+            // Reduce non-head sub expressions
+            vec![fresh(move |sub, res, tail, tt| {
+                all([
+                    list::not_empty(t),
+                    eq(e, cons(sub, tail)),
+                    list::at_least_two(sub),
+                    cond([
+                        vec![eq(tt, cons(res, t)), list::at_least_two(res)],
+                        vec![fresh(move |single| {
+                            all([eq(tt, cons(single, t)), eq(res, list!(single))])
+                        })],
+                    ]),
+                    jield(move || eval_inner(sub, v, v, NULL, res)),
+                    jield(move || eval_inner(tail, v, v_, tt, t_)),
+                ])
+            })],
+        ])
+    }
+);
+
+// `expr` reduces to the λ-term with variables `vars` and body `term`
+goal!(
+    pub fn eval(expr: Var, vars: Var, term: Var) -> Goal {
+        eval_inner(expr, NULL, vars, NULL, term)
+    }
+);
+
+goal!(
+    pub fn is_comb(comb: Var) -> Goal {
+        cond([[eq(comb, "S")], [eq(comb, "K")], [eq(comb, "I")]])
+    }
+);
+
+// `expr` is an expression of combinators, sub expressions have at least two elements
+goal!(
+    pub fn combs(expr: Var) -> Goal {
+        cond([
+            [eq(expr, NULL)],
+            [fresh(move |comb, tail| {
+                all([
+                    eq(expr, cons(comb, tail)),
+                    is_comb(comb),
+                    jield(move || combs(tail)),
+                ])
+            })],
+            [fresh(move |l, tail| {
+                all([
+                    eq(expr, cons(l, tail)),
+                    list::at_least_two(l),
+                    jield(move || combs(l)),
+                    jield(move || combs(tail)),
+                ])
+            })],
+        ])
+    }
+);
+
+// `expr` is a combinator expression for the λ-term with variables `vars` and body `term`
+goal!(
+    pub fn synthesize(expr: Var, vars: Var, term: Var) -> Goal {
+        all([combs(expr), eval(expr, vars, term)])
+    }
+);
+
+/// Expression from text like `S(KS)K`, each character is one combinator or variable
+pub fn parse(s: &str) -> Term {
+    fn inner(chars: &mut impl Iterator<Item = char>) -> Term {
+        match chars.next() {
+            None | Some(')') => NULL,
+            Some('(') => {
+                let sub = inner(chars);
+                cons(sub, inner(chars))
+            }
+            Some(c) => cons(c.to_string(), inner(chars)),
+        }
+    }
+
+    inner(&mut s.chars())
+}
+
+fn reversed(term: &Term) -> Term {
+    match term.to_vec() {
+        Some(items) => items
+            .iter()
+            .fold(NULL, |list, item| cons(reversed(item), list)),
+        None => term.clone(),
+    }
+}
+
+/// Variables and term of a λ-term like `λabc.ac(bc)`, in the reverse order used by `eval`
+pub fn lambda(s: &str) -> Option<(Term, Term)> {
+    let (vars, term) = s.strip_prefix('λ')?.split_once('.')?;
+    Some((reversed(&parse(vars)), reversed(&parse(term))))
+}
+
+fn to_string(term: &Term) -> String {
+    match term {
+        Term::Null => "()".to_string(),
+        Term::String(x) => x.clone(),
+        Term::Cons(_, _) => {
+            let mut result = "(".to_string();
+            let l = term.to_vec().unwrap();
+            result.extend(l.iter().rev().map(to_string));
+            result.push(')');
+            result
+        }
+        _ => "?".to_string(),
+    }
+}
+
+/// Format the variables and term of `eval` as a λ-term
+pub fn fmt_lambda(vars: &Term, terms: &Term) -> Option<String> {
+    let var = Vec::<String>::from_term(vars).ok()?;
+    let term = terms.to_vec()?;
+    let var: String = var.iter().rev().map(String::as_str).collect();
+    let term: String = term.iter().rev().map(to_string).collect();
+    Some(format!("λ{}.{}", var, term))
+}
+
+/// Format a combinator expression like `parse` reads it
+pub fn fmt_expr(expr: &Term) -> String {
+    match expr {
+        Term::Null => "".to_string(),
+        Term::Cons(a, b) => {
+            let mut result = match a.as_ref() {
+                Term::Cons(_, _) => format!("({})", fmt_expr(a)),
+                a => to_string(a),
+            };
+            result.push_str(&fmt_expr(b));
+            result
+        }
+        _ => "?".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    fn check_apply(input: &str, expected: &str) {
+        let expr = parse(input);
+        let result = run(1, |v, e| apply(&expr, e, NULL, v));
+        assert_eq!(AsScheme(result).to_string(), expected, "{input}");
+    }
+
+    fn check_eval(input: &str, expected: &str) {
+        let expr = parse(input);
+        let result = run(1, |vars, term| all([eval(&expr, vars, term), combs(&expr)]));
+        let [vars, term] = result[0].reify();
+        assert_eq!(
+            fmt_lambda(&vars, &term).as_deref(),
+            Some(expected),
+            "{input}"
+        );
+    }
+
+    #[test]
+    fn step() {
+        check_apply("I", "(((a) (a)))");
+        check_apply("Ix", "((() (x)))");
+        check_apply("K", "(((b a) (a)))");
+        check_apply("Kxyz", "((() (x z)))");
+        check_apply("S", "(((c b a) (a c (b c))))");
+        check_apply("Sxy", "(((a) (x a (y a))))");
+        check_apply("Sxyzw", "((() (x z (y z) w)))");
+        check_apply("SKK", "(((a) (K a (K a))))");
+    }
+
+    #[test]
+    fn reduce() {
+        check_eval("I", "λa.a");
+        check_eval("K", "λab.a");
+        check_eval("S", "λabc.ac(bc)");
+        check_eval("KI", "λab.b");
+        check_eval("SKK", "λa.a");
+        check_eval("K(II)(II)", "λa.a");
+        check_eval("S(KS)K", "λabc.a(bc)");
+        check_eval("S(K(SI))K", "λab.ba");
+    }
+
+    #[test]
+    fn birds() {
+        check_eval("S(K((S(KS))K))((S(KS))K)", "λabcd.a(bcd)"); // Blackbird
+        check_eval("S((S(K((S(KS))K)))S)(KK)", "λabc.acb"); // Cardinal
+        check_eval("S((SK)K)((SK)K)", "λa.aa"); // Mocking
+    }
+
+    #[test]
+    fn synthesis() {
+        let (vars, term) = lambda("λab.ba").unwrap();
+        assert_eq!(fmt_lambda(&vars, &term).as_deref(), Some("λab.ba"));
+
+        for (target, expected) in [("λa.a", "I"), ("λab.a", "K"), ("λab.b", "SK")] {
+            let (vars, term) = lambda(target).unwrap();
+            let result = run(1, |expr| synthesize(expr, &vars, &term));
+            assert_eq!(fmt_expr(&result[0].reify()[0]), expected, "{target}");
+        }
+
+        // Round trip through the formatter
+        assert_eq!(fmt_expr(&parse("S(K(SI))K")), "S(K(SI))K");
+    }
+}
@@ -0,0 +1,160 @@
+// Query statistics
+//
+// Queries created with `query_with_stats` or `query_traced` count the work done while they are
+// pulled, see `Query::stats`, other queries don't pay for the counting and timing. Relations
+// defined with `goal!` are counted by module, name and arity, their time includes the goals they
+// call eagerly but not the suspended work they leave in the stream.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::RelationId;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct RelationStats {
+    pub calls: u64,
+    pub time: Duration,
+}
+
+/// Module path, name and arity of a relation
+pub type RelationKey = (&'static str, &'static str, usize);
+
+/// Work done at one search depth, the number of `jield`s passed to get there
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct DepthStats {
+    /// Goals called
+    pub goals: u64,
+    /// Suspended goals run
+    pub resumed: u64,
+    /// Answers of the query
+    pub answers: u64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Unifications of `eq` goals
+    pub unifications: u64,
+    /// Unifications that failed, including violated constraints
+    pub failures: u64,
+    /// Disequality constraints checked against extended substitutions
+    pub constraints_checked: u64,
+    /// Suspended goals created by `jield`
+    pub thunks_created: u64,
+    /// Suspended goals run
+    pub thunks_forced: u64,
+    /// Longest queue of suspended streams
+    pub peak_immature: usize,
+    /// Variables allocated, including the query variables
+    pub fresh_vars: u64,
+    /// Calls of indexed disjunctions that only tried the matching clauses
+    pub index_hits: u64,
+    pub relations: BTreeMap<RelationKey, RelationStats>,
+    /// Work per search depth, see `display::output_search_dot`
+    pub depths: Vec<DepthStats>,
+}
+
+impl Stats {
+    pub(crate) fn relation(&mut self, id: RelationId, arity: usize, time: Duration) {
+        let relation = self
+            .relations
+            .entry((id.module, id.name, arity))
+            .or_default();
+        relation.calls += 1;
+        relation.time += time;
+    }
+
+    pub(crate) fn depth(&mut self, depth: u32) -> &mut DepthStats {
+        let depth = depth as usize;
+        if self.depths.len() <= depth {
+            self.depths.resize(depth + 1, DepthStats::default());
+        }
+        &mut self.depths[depth]
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counters = [
+            ("unifications", self.unifications),
+            ("failures", self.failures),
+            ("constraints checked", self.constraints_checked),
+            ("thunks created", self.thunks_created),
+            ("thunks forced", self.thunks_forced),
+            ("peak immature", self.peak_immature as u64),
+            ("fresh vars", self.fresh_vars),
+            ("index hits", self.index_hits),
+        ];
+        for (name, value) in counters {
+            writeln!(f, "{name:<20} {value:>10}")?;
+        }
+
+        if !self.relations.is_empty() {
+            let names: Vec<_> = self
+                .relations
+                .keys()
+                .map(|(module, name, arity)| format!("{module}::{name}/{arity}"))
+                .collect();
+            let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+            let width = width.max("relation".len());
+            writeln!(f)?;
+            writeln!(f, "{:<width$} {:>10} {:>12}", "relation", "calls", "time")?;
+            for (name, relation) in names.iter().zip(self.relations.values()) {
+                let time = format!("{:.3}ms", relation.time.as_secs_f64() * 1000.0);
+                writeln!(f, "{name:<width$} {:>10} {time:>12}", relation.calls)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn counters() {
+        let mut q = query_with_stats(|x, y| all([neq(x, NULL), list::append(x, y, list!(1, 2))]));
+        assert_eq!(q.iter().count(), 2);
+
+        let stats = q.stats().unwrap();
+        assert_eq!(stats.fresh_vars, 20);
+        assert_eq!(stats.thunks_created, stats.thunks_forced);
+        assert!(stats.failures > 0 && stats.failures < stats.unifications);
+        assert_eq!(stats.relations[&("kanren_rs::list", "append", 3)].calls, 3);
+        assert_eq!(stats.depths.len(), 3);
+        assert_eq!(stats.depths[0].resumed, 0);
+        let resumed: u64 = stats.depths.iter().map(|d| d.resumed).sum();
+        assert_eq!(resumed, stats.thunks_forced);
+        let answers: u64 = stats.depths.iter().map(|d| d.answers).sum();
+        assert_eq!(answers, 2);
+
+        let table = stats.to_string();
+        assert!(table.starts_with("unifications"));
+        assert!(table.contains("\nkanren_rs::list::append/3 "));
+
+        // Off unless asked for
+        assert!(query(|x| list::append(x, NULL, NULL)).stats().is_none());
+    }
+
+    #[test]
+    fn same_names() {
+        let mut q = query_with_stats(|x| {
+            all([
+                list::contains(list!(1, 2), x),
+                relset::contains(list!(1, 2), x),
+                list::removeo(list!(1), x, NULL),
+                map::removeo(list!(cons("a", 1)), "a", NULL),
+            ])
+        });
+        assert_eq!(q.iter().count(), 1);
+
+        let stats = q.stats().unwrap();
+        let calls = |module, name, arity| stats.relations[&(module, name, arity)].calls;
+        assert_eq!(calls("kanren_rs::list", "contains", 2), 3);
+        assert_eq!(calls("kanren_rs::relset", "contains", 2), 3);
+        assert_eq!(calls("kanren_rs::list", "removeo", 3), 3);
+        assert_eq!(calls("kanren_rs::map", "removeo", 3), 1);
+    }
+}
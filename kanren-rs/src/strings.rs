@@ -0,0 +1,195 @@
+// String relations
+//
+// Strings are atoms to unification, these relations look inside them. Text is related to lists
+// of single character strings, see `chars`. Each relation waits with `project_named` until the
+// strings it splits or the parts it joins are bound, and fails on terms that aren't strings. A
+// relation still waiting at the end of a query stays in the answer under its own name.
+
+use crate::*;
+
+/// Text as a list of single character strings
+pub fn chars(s: &str) -> Term {
+    s.chars()
+        .rev()
+        .fold(NULL, |tail, c| cons(c.to_string(), tail))
+}
+
+/// Text from a list of single character strings
+pub fn from_chars(term: &Term) -> Option<String> {
+    term.to_vec()?
+        .into_iter()
+        .map(|c| match c {
+            Term::String(s) => Some(s),
+            _ => None,
+        })
+        .collect()
+}
+
+fn unbound(term: &Term) -> bool {
+    matches!(term, Term::Var(_, _) | Term::Type(_))
+}
+
+/// `chars` is the list of characters of `string`
+pub fn string_charso(string: impl Into<Term>, chars: impl Into<Term>) -> Goal {
+    let terms = [string.into(), chars.into()];
+    project_named("string_charso", terms.clone(), terms, |terms| {
+        let [string, list] = terms else {
+            unreachable!()
+        };
+        match string {
+            Term::String(s) => return Some(eq(list, self::chars(s))),
+            term if !unbound(term) => return Some(fail()),
+            _ => {}
+        }
+
+        // Wait for the whole list, then check it holds single characters
+        let items = list.to_vec();
+        let items = match (items, list) {
+            (Some(items), _) => items,
+            (None, Term::Var(_, _) | Term::Cons(_, _)) => return None,
+            (None, _) => return Some(fail()),
+        };
+        let mut text = String::new();
+        for item in items {
+            match item {
+                Term::String(c) if c.chars().count() == 1 => text.push_str(&c),
+                term if unbound(&term) => return None,
+                _ => return Some(fail()),
+            }
+        }
+        Some(eq(string, text))
+    })
+}
+
+/// `ab` is the string `a` followed by the string `b`
+pub fn concato(a: impl Into<Term>, b: impl Into<Term>, ab: impl Into<Term>) -> Goal {
+    let terms = [a.into(), b.into(), ab.into()];
+    project_named("concato", terms.clone(), terms, |terms| {
+        let [a, b, ab] = terms else { unreachable!() };
+        if [a, b, ab]
+            .iter()
+            .any(|t| !unbound(t) && !matches!(t, Term::String(_)))
+        {
+            return Some(fail());
+        }
+
+        match (a, b, ab) {
+            // Every split of `ab`
+            (_, _, Term::String(s)) => Some(any(s
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([s.len()])
+                .map(|i| all([eq(a, &s[..i]), eq(b, &s[i..])])))),
+            (Term::String(a), Term::String(b), _) => Some(eq(ab, format!("{a}{b}"))),
+            _ => None,
+        }
+    })
+}
+
+/// `prefix` is a prefix of the string `string`
+pub fn prefixo(prefix: impl Into<Term>, string: impl Into<Term>) -> Goal {
+    let terms = [prefix.into(), string.into()];
+    project_named("prefixo", terms.clone(), terms, |terms| {
+        let [prefix, string] = terms else {
+            unreachable!()
+        };
+        if [prefix, string]
+            .iter()
+            .any(|t| !unbound(t) && !matches!(t, Term::String(_)))
+        {
+            return Some(fail());
+        }
+
+        match string {
+            Term::String(s) => Some(any(s
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([s.len()])
+                .map(|i| eq(prefix, &s[..i])))),
+            _ => None,
+        }
+    })
+}
+
+/// `n` is the number of characters of `string`
+pub fn lengtho(string: impl Into<Term>, n: impl Into<Term>) -> Goal {
+    let terms = [string.into(), n.into()];
+    project_named("lengtho", terms.clone(), terms, |terms| {
+        let [string, n] = terms else { unreachable!() };
+        match string {
+            Term::String(s) => Some(eq(n, s.chars().count() as i32)),
+            term if unbound(term) => None,
+            _ => Some(fail()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::AsScheme;
+
+    #[test]
+    fn characters() {
+        let result = run_all(|cs| string_charso("héllo", cs));
+        assert_eq!(AsScheme(result).to_string(), "(((h é l l o)))");
+
+        let result = run_all(|s| string_charso(s, list!("o", "k")));
+        assert_eq!(AsScheme(result).to_string(), "((ok))");
+
+        // Waits until the list is complete
+        let result = run_all(|s, c| all([string_charso(s, list!("o", c)), eq(c, "n")]));
+        assert_eq!(AsScheme(result).to_string(), "((on n))");
+
+        let result = run_all(|s| string_charso(s, list!("ab")));
+        assert_eq!(AsScheme(result).to_string(), "()");
+    }
+
+    #[test]
+    fn concatenation() {
+        let result = run_all(|s| concato("hello", " world", s));
+        assert_eq!(AsScheme(result).to_string(), "((\"hello world\"))");
+
+        let result = run_all(|a, b| concato(a, b, "abc"));
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "(( abc) (a bc) (ab c) (abc ))"
+        );
+
+        let result = run_all(|b| concato("ab", b, "abc"));
+        assert_eq!(AsScheme(result).to_string(), "((c))");
+
+        let result = run_all(|p| all([prefixo(p, "hey"), lengtho(p, 2)]));
+        assert_eq!(AsScheme(result).to_string(), "((he))");
+
+        let result = run_all(|n| lengtho("hello world", n));
+        assert_eq!(AsScheme(result).to_string(), "((11))");
+
+        let result = run_all(|p| prefixo(p, "ab"));
+        assert_eq!(AsScheme(result).to_string(), "(() (a) (ab))");
+
+        let result = run_all(|s| all([prefixo("a", s), eq(s, "abc")]));
+        assert_eq!(AsScheme(result).to_string(), "((abc))");
+    }
+
+    #[test]
+    fn waiting() {
+        let result = run_all(|s, cs| string_charso(s, cons("a", cs)));
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "((_0 _1) : ((string_charso _0 (a . _1))))"
+        );
+
+        let result = run_all(|a, b| concato(a, "!", b));
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "((_0 _1) : ((concato _0 ! _1)))"
+        );
+
+        let result = run_all(|p, s| all([prefixo(p, s), lengtho(s, 2)]));
+        assert_eq!(
+            AsScheme(result).to_string(),
+            "((_0 _1) : ((lengtho _1 2) (prefixo _0 _1)))"
+        );
+    }
+}
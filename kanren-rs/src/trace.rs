@@ -0,0 +1,248 @@
+// Tracing of goal evaluation
+//
+// A `Tracer` passed to `query_traced` is called from `Goal::call` for every goal entered and
+// exited, every goal made during the search, every unification and disequality constraint, every
+// `Yield` suspended and resumed later, and every step of the query and answer found. All methods
+// do nothing by default, so a tracer only implements the events it needs. Tracing is off for
+// queries started with `query`.
+
+use std::cell::{Cell, Ref, RefCell};
+use std::io::Write;
+
+use crate::display::{AsScheme, RelationCall};
+use crate::*;
+
+pub trait Tracer {
+    /// `goal` is called on `state`
+    fn enter(&self, _goal: &Goal, _state: &State) {}
+
+    /// `goal` returned `stream` for `state`
+    fn exit(&self, _goal: &Goal, _state: &State, _stream: &Stream) {}
+
+    /// `goal` was made during the search, like the goal a `project` returned, and is called next.
+    /// It is freed after the call unless the tracer keeps it, and its address reused.
+    fn made(&self, _goal: &Rc<Goal>) {}
+
+    /// Unification of `a` and `b` in `state`, `success` includes checking the constraints
+    fn unify(&self, _a: &Term, _b: &Term, _state: &State, _success: bool) {}
+
+    /// Disequality constraint added to `state`, one of the pairs has to differ
+    fn constraint(&self, _constraint: &Constraint, _state: &State) {}
+
+    /// Evaluation suspended at a `Yield`, continued later at the depth of `state`
+    fn suspend(&self, _state: &State) {}
+
+    /// Suspended evaluation continues
+    fn resume(&self, _state: &State) {}
+
+    /// The query takes its next step, looking for an answer or running a suspended goal
+    fn pull(&self) {}
+
+    /// The query found the answer `state`
+    fn answer(&self, _state: &State) {}
+}
+
+fn term(term: &Term, state: &State) -> String {
+    AsScheme(&deep_resolve(term, &state.map)).to_string()
+}
+
+/// Short description of `goal` with its terms resolved in `state`
+pub fn describe(goal: &Goal, state: &State) -> String {
+    match goal {
+        Goal::Eq(a, b) => format!("eq({}, {})", term(a, state), term(b, state)),
+        Goal::Neq(a, b) => format!("neq({}, {})", term(a, state), term(b, state)),
+        Goal::Both(_, _) => "both".to_string(),
+        Goal::Either(_, _) => "either".to_string(),
+        Goal::Fresh(_) => "fresh".to_string(),
+        Goal::Yield(_) => "yield".to_string(),
+        Goal::Project(terms, _, _) => {
+            let terms: Vec<_> = terms.iter().map(|t| term(t, state)).collect();
+            format!("project({})", terms.join(", "))
+        }
+        Goal::Relation(id, args, _) => {
+            let args: Vec<_> = args.iter().map(|t| deep_resolve(t, &state.map)).collect();
+            RelationCall(id.name, &args).to_string()
+        }
+        Goal::Index(index) => format!("index({})", term(index.term(), state)),
+    }
+}
+
+/// Tracer writing one line per event, indented by nesting of goals and prefixed with the search
+/// depth of the state
+pub struct LogTracer<W: Write> {
+    out: RefCell<W>,
+    level: Cell<usize>,
+}
+
+impl LogTracer<std::io::Stderr> {
+    pub fn stderr() -> Self {
+        Self::new(std::io::stderr())
+    }
+}
+
+impl<W: Write> LogTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: RefCell::new(out),
+            level: Cell::new(0),
+        }
+    }
+
+    /// Output written so far
+    pub fn output(&self) -> Ref<'_, W> {
+        self.out.borrow()
+    }
+
+    fn log(&self, state: &State, event: std::fmt::Arguments) {
+        let indent = "  ".repeat(self.level.get());
+        // Tracing is best effort, a failing writer doesn't stop the query
+        let _ = writeln!(self.out.borrow_mut(), "[{}] {indent}{event}", state.depth);
+    }
+}
+
+impl<W: Write> Tracer for LogTracer<W> {
+    fn enter(&self, goal: &Goal, state: &State) {
+        self.log(state, format_args!("enter {}", describe(goal, state)));
+        self.level.set(self.level.get() + 1);
+    }
+
+    fn exit(&self, goal: &Goal, state: &State, stream: &Stream) {
+        self.level.set(self.level.get().saturating_sub(1));
+        self.log(
+            state,
+            format_args!(
+                "exit {}: {} states, {} suspended",
+                describe(goal, state),
+                stream.mature.len(),
+                stream.immature.len()
+            ),
+        );
+    }
+
+    fn unify(&self, a: &Term, b: &Term, state: &State, success: bool) {
+        let result = if success { "ok" } else { "failed" };
+        self.log(
+            state,
+            format_args!("unify {} = {}: {result}", term(a, state), term(b, state)),
+        );
+    }
+
+    fn constraint(&self, constraint: &Constraint, state: &State) {
+        let pairs: Vec<_> = constraint
+            .iter()
+            .map(|(v, t)| format!("{} != {}", term(&Term::from(*v), state), term(t, state)))
+            .collect();
+        self.log(state, format_args!("constraint {}", pairs.join(" or ")));
+    }
+
+    fn suspend(&self, state: &State) {
+        self.log(state, format_args!("suspend"));
+    }
+
+    fn resume(&self, state: &State) {
+        self.log(state, format_args!("resume"));
+    }
+}
+
+/// Tracer counting the states each goal returned when called, e.g. for `output_dot_heat`. These
+/// are the states passed on to the rest of a conjunction, not answers of the query, so a goal
+/// early in a conjunction can return many states that later goals reject. States found after
+/// resuming a suspended goal count for the goals called on resumption.
+///
+/// Goals are told apart by address, so goals made during the search are kept until the counter
+/// is dropped.
+#[derive(Default)]
+pub struct StateCounter {
+    states: RefCell<HashMap<*const Goal, u64>>,
+    made: RefCell<Vec<Rc<Goal>>>,
+}
+
+impl StateCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// States returned by `goal` so far
+    pub fn states(&self, goal: &Goal) -> u64 {
+        self.states.borrow().get(&(goal as *const _)).copied().unwrap_or(0)
+    }
+
+    /// Most states returned by any goal
+    pub fn max(&self) -> u64 {
+        self.states.borrow().values().copied().max().unwrap_or(0)
+    }
+}
+
+impl Tracer for StateCounter {
+    fn exit(&self, goal: &Goal, _state: &State, stream: &Stream) {
+        *self.states.borrow_mut().entry(goal).or_default() += stream.mature.len() as u64;
+    }
+
+    fn made(&self, goal: &Rc<Goal>) {
+        self.made.borrow_mut().push(goal.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log() {
+        let tracer = Rc::new(LogTracer::new(Vec::new()));
+        let q = query_traced(tracer.clone(), |x| {
+            all([neq(x, 2), any([eq(x, 1), jield(move || eq(x, 2))])])
+        });
+        assert_eq!(q.count(), 1);
+
+        let output = String::from_utf8(tracer.output().clone()).unwrap();
+        assert_eq!(
+            output,
+            "\
+[0] enter both
+[0]   enter neq(_0, 2)
+[0]     constraint _0 != 2
+[0]   exit neq(_0, 2): 1 states, 0 suspended
+[0]   enter either
+[0]     enter eq(_0, 1)
+[0]       unify _0 = 1: ok
+[0]     exit eq(_0, 1): 1 states, 0 suspended
+[0]     enter yield
+[1]       suspend
+[0]     exit yield: 0 states, 1 suspended
+[0]   exit either: 1 states, 1 suspended
+[0] exit both: 1 states, 1 suspended
+[1] resume
+[1] enter eq(_0, 2)
+[1]   unify _0 = 2: failed
+[1] exit eq(_0, 2): 0 states, 0 suspended
+"
+        );
+    }
+
+    #[test]
+    fn relation() {
+        let tracer = Rc::new(LogTracer::new(Vec::new()));
+        let q = query_traced(tracer.clone(), |x| {
+            all([eq(x, list!(1)), list::append(x, list!(2), list!(1, 2))])
+        });
+        assert_eq!(q.count(), 1);
+
+        let output = String::from_utf8(tracer.output().clone()).unwrap();
+        assert!(output.contains("[0]   enter append((1), (2), (1 2))\n"));
+    }
+
+    #[test]
+    fn made_goals() {
+        // Each goal a project returns is kept, so none shares the count of an earlier one
+        let counter = Rc::new(StateCounter::new());
+        let q = query_traced(counter.clone(), |x: Var| {
+            any((0..100).map(|i| project([x.into()], move |_| Some(eq(x, i)))))
+        });
+        assert_eq!(q.count(), 100);
+
+        let made = counter.made.borrow();
+        assert_eq!(made.len(), 100);
+        assert!(made.iter().all(|goal| counter.states(goal) == 1));
+    }
+}